@@ -0,0 +1,522 @@
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use bevy::{input::InputSystem, prelude::*, utils::HashMap};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+/// Where the player's bindings are kept, next to the executable's working directory.
+const BINDINGS_PATH: &str = "bindings.ron";
+
+/// Reads keyboard and gamepads into [`ActionState`], through the
+/// [`InputBindings`] saved in `bindings.ron`.
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        let bindings = match InputBindings::load(BINDINGS_PATH) {
+            Ok(v) => v,
+            Err(err) => {
+                info!("using the default bindings: {:#}", err);
+                InputBindings::default()
+            }
+        };
+        app.insert_resource(bindings)
+            .init_resource::<ActionState>()
+            .init_resource::<Rebinding>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_actions.after(InputSystem))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                capture_rebinding.after(InputSystem).before(update_actions),
+            );
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// What the player can do, whatever the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    /// Axis: left is negative.
+    MoveX,
+    /// Axis: down is negative.
+    MoveY,
+    Run,
+    Jump,
+    Attack,
+    Dash,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::MoveX,
+        Action::MoveY,
+        Action::Run,
+        Action::Jump,
+        Action::Attack,
+        Action::Dash,
+        Action::Pause,
+    ];
+
+    pub fn is_axis(&self) -> bool {
+        matches!(self, Action::MoveX | Action::MoveY)
+    }
+}
+
+/// A key, button or stick driving an action. Keys and buttons give 0 or 1,
+/// pairs of them -1 to 1 like sticks.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    KeyAxis {
+        negative: KeyCode,
+        positive: KeyCode,
+    },
+    Button(GamepadButtonType),
+    ButtonAxis {
+        negative: GamepadButtonType,
+        positive: GamepadButtonType,
+    },
+    Axis(GamepadAxisType),
+}
+
+/// The devices and controls a binding reads; an action keeps one binding
+/// of each kind when rebound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BindingKind {
+    Key,
+    Button,
+    Axis,
+}
+
+impl Binding {
+    fn kind(&self) -> BindingKind {
+        match self {
+            Binding::Key(_) | Binding::KeyAxis { .. } => BindingKind::Key,
+            Binding::Button(_) | Binding::ButtonAxis { .. } => BindingKind::Button,
+            Binding::Axis(_) => BindingKind::Axis,
+        }
+    }
+
+    /// Name shown to the player.
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::KeyAxis { negative, positive } => format!("{:?}/{:?}", negative, positive),
+            Binding::Button(button) => format!("{:?}", button),
+            Binding::ButtonAxis { negative, positive } => {
+                format!("{:?}/{:?}", negative, positive)
+            }
+            Binding::Axis(axis) => format!("{:?}", axis),
+        }
+    }
+
+    fn value(
+        &self,
+        keys: &Input<KeyCode>,
+        gamepads: &Gamepads,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+        deadzone: f32,
+    ) -> f32 {
+        let key = |key: &KeyCode| if keys.pressed(*key) { 1.0 } else { 0.0 };
+        let button = |button: &GamepadButtonType| {
+            let pressed = gamepads
+                .iter()
+                .any(|gamepad| buttons.pressed(GamepadButton(*gamepad, *button)));
+            if pressed {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        match self {
+            Binding::Key(v) => key(v),
+            Binding::KeyAxis { negative, positive } => key(positive) - key(negative),
+            Binding::Button(v) => button(v),
+            Binding::ButtonAxis { negative, positive } => button(positive) - button(negative),
+            Binding::Axis(axis) => {
+                // The stick pushed furthest wins.
+                let value = gamepads
+                    .iter()
+                    .filter_map(|gamepad| axes.get(GamepadAxis(*gamepad, *axis)))
+                    .fold(0.0f32, |a, b| if b.abs() > a.abs() { b } else { a });
+                apply_deadzone(value, deadzone)
+            }
+        }
+    }
+}
+
+/// Zero inside the deadzone, rescaled to reach 1 at the edge of the stick's range.
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    if value.abs() <= deadzone {
+        0.0
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1.0 - deadzone)
+    }
+}
+
+/// Which bindings drive each action, saved as RON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputBindings {
+    /// Stick travel ignored around the centre, from 0 to 1.
+    pub deadzone: f32,
+    /// How far an axis must be pushed to count as pressed.
+    pub press_threshold: f32,
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let bindings = BTreeMap::from([
+            (
+                Action::MoveX,
+                vec![
+                    Binding::KeyAxis {
+                        negative: KeyCode::Left,
+                        positive: KeyCode::Right,
+                    },
+                    Binding::Axis(GamepadAxisType::LeftStickX),
+                    Binding::ButtonAxis {
+                        negative: GamepadButtonType::DPadLeft,
+                        positive: GamepadButtonType::DPadRight,
+                    },
+                ],
+            ),
+            (
+                Action::MoveY,
+                vec![
+                    Binding::KeyAxis {
+                        negative: KeyCode::Down,
+                        positive: KeyCode::Up,
+                    },
+                    Binding::Axis(GamepadAxisType::LeftStickY),
+                    Binding::ButtonAxis {
+                        negative: GamepadButtonType::DPadDown,
+                        positive: GamepadButtonType::DPadUp,
+                    },
+                ],
+            ),
+            (
+                Action::Run,
+                vec![
+                    Binding::Key(KeyCode::Z),
+                    Binding::Button(GamepadButtonType::RightTrigger),
+                ],
+            ),
+            (
+                Action::Jump,
+                vec![
+                    Binding::Key(KeyCode::Space),
+                    Binding::Button(GamepadButtonType::South),
+                ],
+            ),
+            (
+                Action::Attack,
+                vec![
+                    Binding::Key(KeyCode::X),
+                    Binding::Button(GamepadButtonType::West),
+                ],
+            ),
+            (
+                Action::Dash,
+                vec![
+                    Binding::Key(KeyCode::C),
+                    Binding::Button(GamepadButtonType::East),
+                ],
+            ),
+            (
+                Action::Pause,
+                vec![
+                    Binding::Key(KeyCode::Escape),
+                    Binding::Button(GamepadButtonType::Start),
+                ],
+            ),
+        ]);
+        Self {
+            deadzone: 0.2,
+            press_threshold: 0.5,
+            bindings,
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(ron::de::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(self, PrettyConfig::new())?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |v| v.as_slice())
+    }
+
+    /// Replaces the action's bindings of the same kind as `binding`: keys,
+    /// gamepad buttons (D-pad included) or sticks.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        bindings.retain(|v| v.kind() != binding.kind());
+        bindings.push(binding);
+    }
+
+    /// Names of the action's bindings, e.g. `Space / South`.
+    pub fn label(&self, action: Action) -> String {
+        let labels: Vec<String> = self.get(action).iter().map(Binding::label).collect();
+        labels.join(" / ")
+    }
+
+    /// One line per entry, each binding label followed by its description.
+    pub fn help_text(&self, entries: &[(Action, &str)]) -> String {
+        let mut text = String::new();
+        for (action, description) in entries {
+            let _ = writeln!(text, "{} {}", self.label(*action), description);
+        }
+        text
+    }
+}
+
+/// Value of every action this frame, from -1 to 1 for axes and 0 to 1 otherwise.
+#[derive(Default)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    previous: HashMap<Action, f32>,
+    threshold: f32,
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action).abs() >= self.threshold
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && !self.was_pressed(action)
+    }
+
+    fn was_pressed(&self, action: Action) -> bool {
+        self.previous
+            .get(&action)
+            .copied()
+            .unwrap_or_default()
+            .abs()
+            >= self.threshold
+    }
+}
+
+/// Waits for the next key, button or stick push and binds it to an action,
+/// then saves the bindings. Axes take two keys or buttons in a row, the
+/// negative direction first.
+#[derive(Default)]
+pub struct Rebinding {
+    action: Option<Action>,
+    negative: Option<Binding>,
+}
+
+impl Rebinding {
+    pub fn start(&mut self, action: Action) {
+        self.action = Some(action);
+        self.negative = None;
+    }
+
+    pub fn cancel(&mut self) {
+        self.action = None;
+        self.negative = None;
+    }
+
+    /// The action waiting for a binding.
+    pub fn pending(&self) -> Option<Action> {
+        self.action
+    }
+}
+
+fn update_actions(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut state: ResMut<ActionState>,
+) {
+    let state = &mut *state;
+    std::mem::swap(&mut state.previous, &mut state.values);
+    state.values.clear();
+    state.threshold = bindings.press_threshold;
+    // The key being bound does nothing else.
+    if rebinding.action.is_some() {
+        return;
+    }
+    for action in Action::ALL {
+        let value = bindings
+            .get(action)
+            .iter()
+            .map(|binding| binding.value(&keys, &gamepads, &buttons, &axes, bindings.deadzone))
+            .fold(0.0f32, |a, b| if b.abs() > a.abs() { b } else { a });
+        state.values.insert(action, value);
+    }
+}
+
+fn capture_rebinding(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let action = match rebinding.action {
+        Some(v) => v,
+        None => return,
+    };
+
+    let pressed = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Button(button.1))
+        });
+    let stick = if action.is_axis() {
+        gamepads.iter().find_map(|gamepad| {
+            [
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+                GamepadAxisType::RightStickX,
+                GamepadAxisType::RightStickY,
+            ]
+            .into_iter()
+            .find(|axis| {
+                axes.get(GamepadAxis(*gamepad, *axis))
+                    .map_or(false, |v| v.abs() > bindings.press_threshold)
+            })
+            .map(Binding::Axis)
+        })
+    } else {
+        None
+    };
+
+    let binding = match (stick, pressed, rebinding.negative) {
+        (Some(stick), _, None) => stick,
+        (_, Some(pressed), None) if action.is_axis() => {
+            rebinding.negative = Some(pressed);
+            return;
+        }
+        (_, Some(Binding::Key(positive)), Some(Binding::Key(negative))) => {
+            Binding::KeyAxis { negative, positive }
+        }
+        (_, Some(Binding::Button(positive)), Some(Binding::Button(negative))) => {
+            Binding::ButtonAxis { negative, positive }
+        }
+        (_, Some(pressed), None) => pressed,
+        (_, Some(_), Some(_)) => {
+            // A key and a button can't make an axis: start over.
+            rebinding.negative = None;
+            return;
+        }
+        _ => return,
+    };
+    bindings.rebind(action, binding);
+    rebinding.cancel();
+    if let Err(err) = bindings.save(BINDINGS_PATH) {
+        warn!("failed to save the bindings: {:#}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind_replaces_only_the_same_kind() {
+        let mut bindings = InputBindings::default();
+        let button_axis = Binding::ButtonAxis {
+            negative: GamepadButtonType::LeftTrigger,
+            positive: GamepadButtonType::RightTrigger,
+        };
+        bindings.rebind(Action::MoveX, button_axis);
+        assert_eq!(
+            bindings.get(Action::MoveX),
+            [
+                Binding::KeyAxis {
+                    negative: KeyCode::Left,
+                    positive: KeyCode::Right,
+                },
+                Binding::Axis(GamepadAxisType::LeftStickX),
+                button_axis,
+            ]
+        );
+
+        let stick = Binding::Axis(GamepadAxisType::RightStickX);
+        bindings.rebind(Action::MoveX, stick);
+        assert_eq!(
+            bindings.get(Action::MoveX),
+            [
+                Binding::KeyAxis {
+                    negative: KeyCode::Left,
+                    positive: KeyCode::Right,
+                },
+                button_axis,
+                stick,
+            ]
+        );
+
+        let keys = Binding::KeyAxis {
+            negative: KeyCode::A,
+            positive: KeyCode::D,
+        };
+        bindings.rebind(Action::MoveX, keys);
+        assert_eq!(bindings.get(Action::MoveX), [button_axis, stick, keys]);
+
+        bindings.rebind(Action::Jump, Binding::Key(KeyCode::K));
+        assert_eq!(
+            bindings.get(Action::Jump),
+            [
+                Binding::Button(GamepadButtonType::South),
+                Binding::Key(KeyCode::K),
+            ]
+        );
+    }
+
+    #[test]
+    fn deadzone_is_removed_and_rescaled() {
+        assert_eq!(apply_deadzone(0.1, 0.2), 0.0);
+        assert_eq!(apply_deadzone(-0.2, 0.2), 0.0);
+        assert!((apply_deadzone(0.6, 0.2) - 0.5).abs() < 1e-6);
+        assert!((apply_deadzone(-0.6, 0.2) + 0.5).abs() < 1e-6);
+        assert_eq!(apply_deadzone(1.0, 0.2), 1.0);
+        assert_eq!(apply_deadzone(-1.0, 0.2), -1.0);
+    }
+
+    fn state(previous: f32, value: f32) -> ActionState {
+        ActionState {
+            values: HashMap::from_iter([(Action::MoveX, value)]),
+            previous: HashMap::from_iter([(Action::MoveX, previous)]),
+            threshold: 0.5,
+        }
+    }
+
+    #[test]
+    fn just_pressed_crosses_the_threshold() {
+        assert!(state(0.0, 0.5).just_pressed(Action::MoveX));
+        assert!(state(0.0, -0.7).just_pressed(Action::MoveX));
+        assert!(!state(0.0, 0.4).just_pressed(Action::MoveX));
+        assert!(!state(0.6, 0.9).just_pressed(Action::MoveX));
+        assert!(state(0.4, 0.6).just_pressed(Action::MoveX));
+        assert!(!state(0.6, 0.9).just_pressed(Action::Jump));
+        assert!(state(0.6, 0.9).pressed(Action::MoveX));
+    }
+}
@@ -0,0 +1,302 @@
+use std::path::Path;
+
+use bevy::{asset::LoadState, prelude::*};
+use bevy_rapier2d::prelude::{RapierContext, Sensor};
+use serde::Deserialize;
+
+use super::{
+    input::{Action, ActionState},
+    libs::{MapLoaded, MapUnloaded, TiledMap, TiledMapBundle, TiledObjectAppExt},
+    playing::{Pause, Player},
+    GameState,
+};
+
+/// Seconds taken to fade to black, and again to fade back in.
+const FADE_TIME: f32 = 0.4;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_tiled_object("spawn_point", |entity, object| {
+            entity.insert(SpawnPoint {
+                name: object.name.to_string(),
+            });
+        });
+        app.register_tiled_object("door", |entity, object| {
+            match object.properties.deserialize::<Door>() {
+                Ok(door) => entity.insert(door),
+                Err(err) => {
+                    warn!("door {:?} has invalid properties: {}", object.name, err);
+                    return;
+                }
+            };
+            if let Some(collider) = object.collider() {
+                entity.insert(collider).insert(Sensor);
+            }
+        });
+        app.init_resource::<LevelManager>()
+            .add_event::<LevelEntered>()
+            .add_system_set(SystemSet::on_enter(GameState::Start).with_system(spawn_fade))
+            .add_system_set(
+                SystemSet::on_update(GameState::Start)
+                    .with_system(run_transition)
+                    .with_system(use_doors),
+            );
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Loads levels, i.e. Tiled maps, one at a time. Loading one fades the
+/// screen out, replaces the current map and everything spawned from it,
+/// then sends [`LevelEntered`] with the spawn point to put the player at.
+pub struct LevelManager {
+    /// Map that is loaded, or being loaded.
+    current: Option<String>,
+    map: Option<Entity>,
+    request: Option<LevelRequest>,
+    /// Last level entered, returned to when the next one fails to load.
+    entered: Option<LevelRequest>,
+    transition: Transition,
+    /// Opacity of the fade overlay. The game starts on black.
+    fade: f32,
+}
+
+impl Default for LevelManager {
+    fn default() -> Self {
+        Self {
+            current: None,
+            map: None,
+            request: None,
+            entered: None,
+            transition: Transition::Idle,
+            fade: 1.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct LevelRequest {
+    map: String,
+    spawn: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transition {
+    Idle,
+    FadeOut,
+    Loading(Entity),
+    Entering(Entity),
+    FadeIn,
+}
+
+impl LevelManager {
+    /// Switches to the map named `map` (`tiled/<map>.tmx`, or `tiled/<map>`
+    /// when it has an extension), entering it at the spawn point named `spawn`.
+    pub fn load(&mut self, map: impl Into<String>, spawn: impl Into<String>) {
+        self.request = Some(LevelRequest {
+            map: map.into(),
+            spawn: spawn.into(),
+        });
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition != Transition::Idle || self.request.is_some()
+    }
+}
+
+/// Sent once a level is spawned, with where the player enters it.
+pub struct LevelEntered {
+    pub map: String,
+    pub position: Vec3,
+}
+
+/// Where the player appears, placed in Tiled as a `spawn_point` object.
+#[derive(Component)]
+pub struct SpawnPoint {
+    pub name: String,
+}
+
+/// A `door` object: leads to spawn point `target_spawn` of map `target_map`.
+/// Entered by pushing up while touching it, or on touch when `auto` is set.
+#[derive(Component, Deserialize, Debug, Clone)]
+pub struct Door {
+    pub target_map: String,
+    pub target_spawn: String,
+    #[serde(default)]
+    pub auto: bool,
+}
+
+#[derive(Component)]
+struct FadeOverlay;
+
+fn map_path(name: &str) -> String {
+    if Path::new(name).extension().is_some() {
+        format!("tiled/{}", name)
+    } else {
+        format!("tiled/{}.tmx", name)
+    }
+}
+
+fn spawn_fade(mut commands: Commands, levels: Res<LevelManager>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            color: UiColor(Color::rgba(0.0, 0.0, 0.0, levels.fade)),
+            ..default()
+        })
+        .insert(FadeOverlay);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_transition(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut levels: ResMut<LevelManager>,
+    mut loaded_events: EventReader<MapLoaded>,
+    mut entered_events: EventWriter<LevelEntered>,
+    mut unloaded_events: EventWriter<MapUnloaded>,
+    spawn_points: Query<(&SpawnPoint, &Parent, &Transform)>,
+    maps: Query<(&Transform, &Handle<TiledMap>)>,
+    mut overlay: Query<&mut UiColor, With<FadeOverlay>>,
+) {
+    let loaded: Vec<Entity> = loaded_events.iter().map(|event| event.entity).collect();
+    let step = time.delta_seconds() / FADE_TIME;
+
+    match levels.transition {
+        Transition::Idle => {
+            if levels.request.is_some() {
+                levels.transition = Transition::FadeOut;
+            }
+        }
+        Transition::FadeOut => {
+            levels.fade = (levels.fade + step).min(1.0);
+            if levels.fade >= 1.0 {
+                // The screen is black: swap the maps.
+                if let Some(map) = levels.map.take() {
+                    // The tiled plugin only reports the maps it unloads itself.
+                    if let Ok((_, handle)) = maps.get(map) {
+                        unloaded_events.send(MapUnloaded {
+                            entity: map,
+                            map: handle.clone_weak(),
+                        });
+                    }
+                    commands.entity(map).despawn_recursive();
+                }
+                let name = levels.request.as_ref().unwrap().map.clone();
+                let map = commands
+                    .spawn_bundle(TiledMapBundle {
+                        tiled_map: asset_server.load(&map_path(&name)),
+                        ..default()
+                    })
+                    .id();
+                levels.current = Some(name);
+                levels.map = Some(map);
+                levels.transition = Transition::Loading(map);
+            }
+        }
+        Transition::Loading(map) => {
+            let failed = matches!(
+                maps.get(map),
+                Ok((_, handle)) if asset_server.get_load_state(handle) == LoadState::Failed
+            );
+            // Its entities exist from the next frame on.
+            if loaded.contains(&map) {
+                levels.transition = Transition::Entering(map);
+            } else if failed {
+                let request = levels.request.take().unwrap();
+                error!("failed to load map {:?}", request.map);
+                commands.entity(map).despawn_recursive();
+                levels.map = None;
+                levels.current = None;
+                // Go back to the previous level, or show the empty world.
+                match levels.entered.clone() {
+                    Some(entered) if entered.map != request.map => {
+                        levels.request = Some(entered);
+                        levels.transition = Transition::FadeOut;
+                    }
+                    _ => levels.transition = Transition::FadeIn,
+                }
+            }
+        }
+        Transition::Entering(map) => {
+            let request = levels.request.take().unwrap();
+            let map_transform = maps
+                .get(map)
+                .map(|(transform, _)| *transform)
+                .unwrap_or_default();
+            let points: Vec<(&SpawnPoint, &Transform)> = spawn_points
+                .iter()
+                .filter(|(_, parent, _)| parent.get() == map)
+                .map(|(point, _, transform)| (point, transform))
+                .collect();
+            let point = match points.iter().find(|(point, _)| point.name == request.spawn) {
+                Some(v) => Some(v),
+                None => {
+                    warn!(
+                        "map {:?} has no spawn point {:?}",
+                        request.map, request.spawn
+                    );
+                    points.first()
+                }
+            };
+            let position = match point {
+                Some((_, transform)) => map_transform.mul_transform(**transform).translation,
+                None => map_transform.translation,
+            };
+            entered_events.send(LevelEntered {
+                map: request.map.clone(),
+                position,
+            });
+            levels.entered = Some(request);
+            levels.transition = Transition::FadeIn;
+        }
+        Transition::FadeIn => {
+            levels.fade = (levels.fade - step).max(0.0);
+            if levels.fade <= 0.0 {
+                levels.transition = Transition::Idle;
+            }
+        }
+    }
+
+    for mut color in &mut overlay {
+        color.0.set_a(levels.fade);
+    }
+}
+
+fn use_doors(
+    actions: Res<ActionState>,
+    pause: Res<Pause>,
+    rapier_context: Res<RapierContext>,
+    mut levels: ResMut<LevelManager>,
+    players: Query<Entity, With<Player>>,
+    doors: Query<(Entity, &Door)>,
+) {
+    if levels.is_transitioning() || pause.paused {
+        return;
+    }
+    let pressed = actions.just_pressed(Action::MoveY) && actions.value(Action::MoveY) > 0.0;
+    for player in &players {
+        for (entity, door) in &doors {
+            if !(door.auto || pressed) {
+                continue;
+            }
+            if rapier_context.intersection_pair(player, entity) == Some(true) {
+                levels.load(door.target_map.clone(), door.target_spawn.clone());
+                return;
+            }
+        }
+    }
+}
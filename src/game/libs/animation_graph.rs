@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use super::dragon_player::{DragonAnimationEvent, DragonAnimationPlayer, DragonAnimationSystem};
+
+#[derive(Default)]
+pub struct AnimationGraphPlugin;
+
+impl Plugin for AnimationGraphPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationGraph>()
+            .init_asset_loader::<AnimationGraphLoader>()
+            .add_system(run_state_machines.before(DragonAnimationSystem::Advance));
+    }
+}
+
+#[derive(Default)]
+pub struct AnimationGraphLoader;
+
+impl AssetLoader for AnimationGraphLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let graph: AnimationGraph = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(graph));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim_graph.ron"]
+    }
+}
+
+/// Animation states, each playing one clip, and the transitions between them.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "c0c7c2a4-3b0e-4a53-9d0c-5e8f0b6f4a17"]
+pub struct AnimationGraph {
+    pub initial: String,
+    pub states: HashMap<String, AnimationState>,
+    /// Transitions tried for every state listed in `from`, after that state's own ones.
+    #[serde(default)]
+    pub shared: Vec<SharedTransition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimationState {
+    pub clip: String,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transition {
+    pub to: String,
+    #[serde(default)]
+    pub when: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SharedTransition {
+    pub from: Vec<String>,
+    pub to: String,
+    #[serde(default)]
+    pub when: Vec<Condition>,
+}
+
+/// A transition is taken when all of its conditions hold.
+#[derive(Debug, Clone, Deserialize)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    IsTrue(String),
+    IsFalse(String),
+    /// Holds while the trigger is set; taking the transition consumes it.
+    Trigger(String),
+    /// The current clip looped or finished since the last update.
+    ClipFinished,
+}
+
+/// Runs an [`AnimationGraph`] and plays the current state's clip on the
+/// entity's [`DragonAnimationPlayer`]. Game code only feeds the parameters.
+#[derive(Component, Default)]
+pub struct AnimationStateMachine {
+    pub graph: Handle<AnimationGraph>,
+    state: Option<String>,
+    floats: HashMap<String, f32>,
+    bools: HashMap<String, bool>,
+    triggers: HashSet<String>,
+    clip_finished: bool,
+}
+
+impl AnimationStateMachine {
+    pub fn new(graph: Handle<AnimationGraph>) -> Self {
+        Self { graph, ..default() }
+    }
+
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        self.floats.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.floats.insert(name.to_string(), value);
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        self.bools.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.bools.insert(name.to_string(), value);
+    }
+
+    pub fn set_trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+
+    /// Whether a transition out of the current state waits for the trigger
+    /// `name`. Triggers persist until taken, so setting one no state listens
+    /// to would fire it in some later state.
+    pub fn listens_to(&self, graph: &AnimationGraph, name: &str) -> bool {
+        let current = self.state.as_deref().unwrap_or(&graph.initial);
+        let waits = |when: &[Condition]| {
+            when.iter()
+                .any(|it| matches!(it, Condition::Trigger(trigger) if trigger == name))
+        };
+        let own = graph.states.get(current).map_or(false, |state| {
+            state.transitions.iter().any(|it| waits(&it.when))
+        });
+        own || graph
+            .shared
+            .iter()
+            .any(|it| it.from.iter().any(|from| from == current) && waits(&it.when))
+    }
+
+    fn check(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Greater(name, value) => self.float(name) > *value,
+            Condition::Less(name, value) => self.float(name) < *value,
+            Condition::IsTrue(name) => self.bool(name),
+            Condition::IsFalse(name) => !self.bool(name),
+            Condition::Trigger(name) => self.triggers.contains(name),
+            Condition::ClipFinished => self.clip_finished,
+        }
+    }
+
+    /// Takes at most one transition and returns the clip of the current state.
+    fn step<'a>(&mut self, graph: &'a AnimationGraph) -> Option<&'a str> {
+        // A reloaded graph may have dropped the state we were in.
+        let current = match self.state.take() {
+            Some(state) if graph.states.contains_key(&state) => state,
+            _ => graph.initial.clone(),
+        };
+        let state = graph.states.get(&current)?;
+
+        let shared = graph
+            .shared
+            .iter()
+            .filter(|it| it.from.contains(&current))
+            .map(|it| (&it.to, &it.when));
+        let next = state
+            .transitions
+            .iter()
+            .map(|it| (&it.to, &it.when))
+            .chain(shared)
+            .find(|(to, when)| {
+                graph.states.contains_key(*to) && when.iter().all(|it| self.check(it))
+            });
+
+        let current = match next {
+            Some((to, when)) => {
+                for condition in when.iter() {
+                    if let Condition::Trigger(name) = condition {
+                        self.triggers.remove(name);
+                    }
+                }
+                to.clone()
+            }
+            None => current,
+        };
+
+        self.clip_finished = false;
+        let clip = graph.states.get(&current).map(|it| it.clip.as_str());
+        self.state = Some(current);
+        clip
+    }
+}
+
+/// Steps every state machine; feed the parameters before it.
+pub fn run_state_machines(
+    graphs: Res<Assets<AnimationGraph>>,
+    mut events: EventReader<DragonAnimationEvent>,
+    mut query: Query<(
+        Entity,
+        &mut AnimationStateMachine,
+        &mut DragonAnimationPlayer,
+    )>,
+) {
+    let clip_ended: Vec<(Entity, String)> = events
+        .iter()
+        .map(|eve| match eve {
+            DragonAnimationEvent::Looped { entity, animation }
+            | DragonAnimationEvent::Finished { entity, animation } => (*entity, animation.clone()),
+        })
+        .collect();
+
+    for (entity, mut machine, mut player) in &mut query {
+        let graph = match graphs.get(&machine.graph) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        if clip_ended
+            .iter()
+            .any(|(it, animation)| *it == entity && animation == player.animation())
+        {
+            machine.clip_finished = true;
+        }
+
+        if let Some(clip) = machine.step(graph) {
+            player.play(clip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAPH: &str = r#"(
+        initial: "idle",
+        states: {
+            "idle": (
+                clip: "idle_clip",
+                transitions: [(to: "walk", when: [Greater("speed", 10.0)])],
+            ),
+            "walk": (
+                clip: "walk_clip",
+                transitions: [(to: "idle", when: [Less("speed", 10.0)])],
+            ),
+            "jump": (
+                clip: "jump_clip",
+                transitions: [(to: "idle", when: [ClipFinished, IsTrue("is_ground")])],
+            ),
+        },
+        shared: [
+            (from: ["idle", "walk"], to: "jump", when: [Trigger("jump")]),
+            (from: ["idle"], to: "missing", when: []),
+        ],
+    )"#;
+
+    fn graph() -> AnimationGraph {
+        ron::de::from_str(GRAPH).unwrap()
+    }
+
+    #[test]
+    fn starts_in_initial_state() {
+        let graph = graph();
+        let mut machine = AnimationStateMachine::default();
+        assert_eq!(machine.step(&graph), Some("idle_clip"));
+        assert_eq!(machine.state(), Some("idle"));
+    }
+
+    #[test]
+    fn takes_one_transition_per_step() {
+        let graph = graph();
+        let mut machine = AnimationStateMachine::default();
+        machine.step(&graph);
+
+        machine.set_float("speed", 50.0);
+        assert_eq!(machine.step(&graph), Some("walk_clip"));
+        machine.set_float("speed", 0.0);
+        assert_eq!(machine.step(&graph), Some("idle_clip"));
+        // Transitions to unknown states are skipped.
+        assert_eq!(machine.step(&graph), Some("idle_clip"));
+    }
+
+    #[test]
+    fn own_transitions_come_before_shared_ones() {
+        let graph = graph();
+        let mut machine = AnimationStateMachine::default();
+        machine.step(&graph);
+
+        machine.set_float("speed", 50.0);
+        machine.set_trigger("jump");
+        assert_eq!(machine.step(&graph), Some("walk_clip"));
+        // The trigger waits until a transition takes it.
+        assert_eq!(machine.step(&graph), Some("jump_clip"));
+        assert!(machine.triggers.is_empty());
+    }
+
+    #[test]
+    fn clip_finished_lasts_one_step() {
+        let graph = graph();
+        let mut machine = AnimationStateMachine::default();
+        machine.step(&graph);
+        machine.set_trigger("jump");
+        machine.step(&graph);
+
+        machine.clip_finished = true;
+        assert_eq!(machine.step(&graph), Some("jump_clip"));
+        machine.set_bool("is_ground", true);
+        assert_eq!(machine.step(&graph), Some("jump_clip"));
+        machine.clip_finished = true;
+        assert_eq!(machine.step(&graph), Some("idle_clip"));
+    }
+
+    #[test]
+    fn listens_to_own_and_shared_triggers() {
+        let graph = graph();
+        let mut machine = AnimationStateMachine::default();
+        // Before the first step, the initial state.
+        assert!(machine.listens_to(&graph, "jump"));
+        assert!(!machine.listens_to(&graph, "attack"));
+
+        machine.set_trigger("jump");
+        machine.step(&graph);
+        machine.step(&graph);
+        assert_eq!(machine.state(), Some("jump"));
+        assert!(!machine.listens_to(&graph, "jump"));
+    }
+
+    #[test]
+    fn unknown_state_restarts_from_initial() {
+        let graph = graph();
+        let mut machine = AnimationStateMachine {
+            state: Some("gone".to_string()),
+            ..default()
+        };
+        assert_eq!(machine.step(&graph), Some("idle_clip"));
+    }
+}
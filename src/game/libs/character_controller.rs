@@ -0,0 +1,874 @@
+//! Kinematic platformer movement: characters are moved by shape casts against
+//! the physics world instead of being pushed around by the solver.
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::{
+    ActiveCollisionTypes, Collider, QueryFilter, RapierConfiguration, RapierContext, RigidBody, Toi,
+};
+
+use super::one_way_platform::{DropThrough, OneWayPlatform};
+
+/// Times a move may be deflected by what it hits in one frame.
+const MAX_SLIDES: usize = 4;
+
+/// Colliders a single move may skip, e.g. one-way platforms hit from below.
+const MAX_IGNORED: usize = 4;
+
+pub struct CharacterControllerPlugin;
+
+impl Plugin for CharacterControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(move_characters);
+    }
+}
+
+/// How a character moves, in pixels and seconds. Tune it freely: nothing
+/// else reads the animation to decide how fast the character goes.
+#[derive(Component, Clone, Debug)]
+pub struct CharacterController {
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    /// Gain of speed towards the input's, per second.
+    pub acceleration: f32,
+    /// Loss of speed when there is no input, or it points the other way.
+    pub deceleration: f32,
+    /// Share of the acceleration and deceleration kept in the air.
+    pub air_control: f32,
+    /// The jump, which also sets the gravity.
+    pub jump: JumpModel,
+    /// Sliding down, jumping off and hanging from walls.
+    pub wall: WallModel,
+    pub dash: DashModel,
+    pub max_fall_speed: f32,
+    /// Steepest ground the character stands and walks on, in radians.
+    pub max_slope: f32,
+    /// Highest ledge climbed without jumping.
+    pub step_height: f32,
+    /// How far the character is pulled down to stay on descending ground.
+    pub snap_distance: f32,
+    /// Gap kept between the collider and what it touches.
+    pub skin: f32,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            walk_speed: 100.0,
+            run_speed: 160.0,
+            acceleration: 1200.0,
+            deceleration: 1600.0,
+            air_control: 0.5,
+            jump: default(),
+            wall: default(),
+            dash: default(),
+            max_fall_speed: 900.0,
+            max_slope: 0.8,
+            step_height: 10.0,
+            snap_distance: 8.0,
+            skin: 0.5,
+        }
+    }
+}
+
+/// A jump described in world units, from which the controller derives the
+/// gravity and the launch speed.
+#[derive(Clone, Debug)]
+pub struct JumpModel {
+    /// Height of a full jump, in pixels.
+    pub apex_height: f32,
+    /// Seconds taken to reach the apex.
+    pub time_to_apex: f32,
+    /// Seconds after leaving the ground during which a jump is still allowed.
+    pub coyote_time: f32,
+    /// Seconds a jump pressed in the air is remembered, to fire on landing.
+    pub buffer_time: f32,
+    /// Factor applied to the upward speed when the button is released while
+    /// rising: 1 always jumps to the apex, 0 stops at once.
+    pub release_cut: f32,
+}
+
+impl Default for JumpModel {
+    fn default() -> Self {
+        Self {
+            apex_height: 100.0,
+            time_to_apex: 0.33,
+            coyote_time: 0.1,
+            buffer_time: 0.12,
+            release_cut: 0.5,
+        }
+    }
+}
+
+impl JumpModel {
+    /// Downward acceleration, in pixels per second squared.
+    pub fn gravity(&self) -> f32 {
+        2.0 * self.apex_height / (self.time_to_apex * self.time_to_apex)
+    }
+
+    /// Upward speed given at the start of a jump, in pixels per second.
+    pub fn impulse(&self) -> f32 {
+        2.0 * self.apex_height / self.time_to_apex
+    }
+}
+
+/// How characters cling to walls, in pixels and seconds.
+#[derive(Clone, Debug)]
+pub struct WallModel {
+    /// Fastest fall while pushing against a wall.
+    pub slide_speed: f32,
+    /// Speed given by a wall jump: away from the wall, and up.
+    pub jump_velocity: Vec2,
+    /// Seconds after a wall jump during which the horizontal input is ignored,
+    /// so that pushing against the wall doesn't cancel it.
+    pub jump_lock: f32,
+    /// How far the side casts look for a wall.
+    pub probe: f32,
+    /// How far above the character a ledge is still grabbed.
+    pub ledge_reach: f32,
+    /// How far past the wall the character stands after climbing a ledge.
+    pub ledge_inset: f32,
+    /// How far below standing on the ledge the character hangs from it.
+    pub ledge_hang: f32,
+}
+
+impl Default for WallModel {
+    fn default() -> Self {
+        Self {
+            slide_speed: 120.0,
+            jump_velocity: Vec2::new(260.0, 560.0),
+            jump_lock: 0.15,
+            probe: 2.0,
+            ledge_reach: 24.0,
+            ledge_inset: 16.0,
+            ledge_hang: 40.0,
+        }
+    }
+}
+
+/// A burst of horizontal speed that ignores gravity, in pixels and seconds.
+#[derive(Clone, Debug)]
+pub struct DashModel {
+    pub speed: f32,
+    pub duration: f32,
+    /// Seconds from the end of a dash before the next one.
+    pub cooldown: f32,
+    /// Dashes allowed before touching the ground or a wall again.
+    pub air_dashes: u32,
+}
+
+impl Default for DashModel {
+    fn default() -> Self {
+        Self {
+            speed: 420.0,
+            duration: 0.18,
+            cooldown: 0.3,
+            air_dashes: 1,
+        }
+    }
+}
+
+/// What the character is asked to do, set by the game every frame.
+#[derive(Component, Default, Debug, Clone)]
+pub struct CharacterInput {
+    /// Horizontal direction, from -1 to 1.
+    pub move_x: f32,
+    /// Vertical direction, from -1 to 1: up climbs a grabbed ledge, down lets go.
+    pub move_y: f32,
+    pub run: bool,
+    /// Jump pressed this frame; fires when standing, or shortly after.
+    pub jump: bool,
+    /// Jump button held: releasing it while rising cuts the jump short.
+    pub jump_held: bool,
+    /// Dash pressed this frame.
+    pub dash: bool,
+}
+
+/// Where the character stands and how it moves, written by the controller
+/// and only read elsewhere.
+#[derive(Component, Debug, Clone)]
+pub struct CharacterState {
+    pub velocity: Vec2,
+    pub grounded: bool,
+    /// Normal of the ground, up when in the air.
+    pub ground_normal: Vec2,
+    pub ground: Option<Entity>,
+    /// 1 when facing right, -1 when facing left.
+    pub facing: f32,
+    /// Started a jump this frame.
+    pub jumped: bool,
+    pub hit_ceiling: bool,
+    pub hit_wall: bool,
+    /// Side of the wall next to the character in the air: -1 left, 1 right, 0 none.
+    pub wall: f32,
+    /// Pushing against a wall while falling, which slows the fall.
+    pub wall_sliding: bool,
+    /// Jumped off a wall this frame.
+    pub wall_jumped: bool,
+    /// Hanging from a ledge.
+    pub hanging: bool,
+    pub dashing: bool,
+    /// Where the character stands once the grabbed ledge is climbed.
+    ledge: Vec2,
+    /// Time left before the horizontal input applies again after a wall jump.
+    wall_jump_lock: f32,
+    /// Time left in the current dash, then before the next one.
+    dash_time: f32,
+    dash_cooldown: f32,
+    air_dashes: u32,
+    /// Time left to jump after leaving the ground.
+    coyote: f32,
+    /// Time left for a pressed jump to fire.
+    buffered_jump: f32,
+    /// Rising from a jump that releasing the button still cuts.
+    cuttable: bool,
+}
+
+impl Default for CharacterState {
+    fn default() -> Self {
+        Self {
+            velocity: Vec2::ZERO,
+            grounded: false,
+            ground_normal: Vec2::Y,
+            ground: None,
+            facing: 1.0,
+            jumped: false,
+            hit_ceiling: false,
+            hit_wall: false,
+            wall: 0.0,
+            wall_sliding: false,
+            wall_jumped: false,
+            hanging: false,
+            dashing: false,
+            ledge: Vec2::ZERO,
+            wall_jump_lock: 0.0,
+            dash_time: 0.0,
+            dash_cooldown: 0.0,
+            air_dashes: 0,
+            coyote: 0.0,
+            buffered_jump: 0.0,
+            cuttable: false,
+        }
+    }
+}
+
+impl CharacterState {
+    /// Stops the character, e.g. when teleporting it.
+    pub fn reset(&mut self) {
+        *self = Self {
+            facing: self.facing,
+            ..default()
+        };
+    }
+}
+
+/// A kinematic character; add a [`Collider`] next to it.
+#[derive(Bundle)]
+pub struct CharacterControllerBundle {
+    pub controller: CharacterController,
+    pub input: CharacterInput,
+    pub state: CharacterState,
+    pub rigid_body: RigidBody,
+    /// Kinematic bodies only meet static sensors (doors...) when asked to.
+    pub collision_types: ActiveCollisionTypes,
+}
+
+impl Default for CharacterControllerBundle {
+    fn default() -> Self {
+        Self {
+            controller: default(),
+            input: default(),
+            state: default(),
+            rigid_body: RigidBody::KinematicPositionBased,
+            collision_types: ActiveCollisionTypes::default()
+                | ActiveCollisionTypes::KINEMATIC_STATIC,
+        }
+    }
+}
+
+/// Casts of one character's collider.
+struct Caster<'a, 'w, 's> {
+    context: &'a RapierContext,
+    entity: Entity,
+    collider: &'a Collider,
+    platforms: &'a Query<'w, 's, (), With<OneWayPlatform>>,
+    /// Whether one-way platforms let the character through.
+    passing: bool,
+    min_ground_y: f32,
+}
+
+impl Caster<'_, '_, '_> {
+    /// First thing hit moving from `position` by `motion`, with the surface
+    /// normal. Skips what the character already overlaps and moves out of,
+    /// and one-way platforms unless landed on from above.
+    fn cast(&self, position: Vec2, motion: Vec2) -> Option<(Entity, Toi, Vec2)> {
+        let mut ignored: Vec<Entity> = Vec::with_capacity(MAX_IGNORED);
+        for _ in 0..=MAX_IGNORED {
+            let predicate = |entity| {
+                entity != self.entity
+                    && !ignored.contains(&entity)
+                    && !(self.passing && self.platforms.contains(entity))
+            };
+            let filter = QueryFilter::default()
+                .exclude_sensors()
+                .exclude_dynamic()
+                .predicate(&predicate);
+            let (entity, toi) =
+                self.context
+                    .cast_shape(position, 0.0, motion, self.collider, 1.0, filter)?;
+            // The obstacle is the first shape of the cast: its outward normal
+            // points back at the character.
+            let normal = toi.normal1.normalize_or_zero();
+            let skip = if self.platforms.contains(entity) {
+                motion.y >= 0.0 || toi.toi <= 0.0 || normal.y < self.min_ground_y
+            } else {
+                toi.toi <= 0.0 && motion.dot(normal) >= 0.0
+            };
+            if !skip {
+                return Some((entity, toi, normal));
+            }
+            ignored.push(entity);
+        }
+        None
+    }
+}
+
+/// Moves characters by their velocity, sliding along what they hit. They
+/// stand still while the physics pipeline is stopped, e.g. when paused.
+pub fn move_characters(
+    time: Res<Time>,
+    rapier_config: Res<RapierConfiguration>,
+    rapier_context: Res<RapierContext>,
+    platforms: Query<(), With<OneWayPlatform>>,
+    mut characters: Query<(
+        Entity,
+        &CharacterController,
+        &CharacterInput,
+        &mut CharacterState,
+        &Collider,
+        &mut Transform,
+        Option<&DropThrough>,
+    )>,
+) {
+    let dt = time.delta_seconds();
+    if dt <= 0.0 || !rapier_config.physics_pipeline_active {
+        return;
+    }
+
+    for (entity, controller, input, mut state, collider, mut transform, drop) in &mut characters {
+        let min_ground_y = controller.max_slope.cos();
+        let was_grounded = state.grounded;
+
+        let wall = &controller.wall;
+        let jump = &controller.jump;
+        let dash = &controller.dash;
+
+        let mut position = transform.translation.truncate();
+
+        state.jumped = false;
+        state.wall_jumped = false;
+        state.buffered_jump = if input.jump {
+            jump.buffer_time
+        } else {
+            state.buffered_jump - dt
+        };
+        state.coyote = if was_grounded {
+            jump.coyote_time
+        } else {
+            state.coyote - dt
+        };
+        state.wall_jump_lock = (state.wall_jump_lock - dt).max(0.0);
+        state.dash_cooldown = (state.dash_cooldown - dt).max(0.0);
+        if was_grounded || state.wall != 0.0 {
+            state.air_dashes = dash.air_dashes;
+        }
+
+        if state.hanging {
+            if input.move_y > 0.5 {
+                // Climb onto the ledge.
+                state.hanging = false;
+                position = state.ledge;
+                state.velocity = Vec2::ZERO;
+                state.grounded = true;
+                state.ground_normal = Vec2::Y;
+                transform.translation.x = position.x;
+                transform.translation.y = position.y;
+                continue;
+            } else if state.buffered_jump > 0.0 {
+                state.hanging = false;
+                state.velocity = Vec2::new(0.0, jump.impulse());
+                state.jumped = true;
+                state.cuttable = true;
+                state.buffered_jump = 0.0;
+            } else if input.move_y < -0.5 || input.move_x * state.wall < 0.0 {
+                state.hanging = false;
+            } else {
+                state.velocity = Vec2::ZERO;
+                continue;
+            }
+        }
+
+        let move_x = if state.wall_jump_lock > 0.0 {
+            0.0
+        } else {
+            input.move_x.clamp(-1.0, 1.0)
+        };
+        // A dash keeps its direction.
+        if move_x != 0.0 && !state.dashing {
+            state.facing = move_x.signum();
+        }
+
+        if state.dashing {
+            state.dash_time -= dt;
+            if state.dash_time <= 0.0 {
+                state.dashing = false;
+                state.dash_cooldown = dash.cooldown;
+            }
+        } else if input.dash && state.dash_cooldown <= 0.0 && (was_grounded || state.air_dashes > 0)
+        {
+            if !was_grounded {
+                state.air_dashes -= 1;
+            }
+            state.dashing = true;
+            state.dash_time = dash.duration;
+            state.cuttable = false;
+        }
+
+        // Horizontal speed, eased towards the input's.
+        let top_speed = if input.run {
+            controller.run_speed
+        } else {
+            controller.walk_speed
+        };
+        let target = move_x * top_speed;
+        let speeding_up = target != 0.0
+            && (state.velocity.x == 0.0
+                || target.signum() == state.velocity.x.signum()
+                    && target.abs() > state.velocity.x.abs());
+        let mut rate = if speeding_up {
+            controller.acceleration
+        } else {
+            controller.deceleration
+        };
+        if !was_grounded {
+            rate *= controller.air_control;
+        }
+        if state.dashing {
+            state.velocity.x = state.facing * dash.speed;
+        } else if state.wall_jump_lock <= 0.0 {
+            let step = rate * dt;
+            state.velocity.x += (target - state.velocity.x).clamp(-step, step);
+        }
+
+        // Vertical speed.
+        if was_grounded && state.velocity.y < 0.0 {
+            state.velocity.y = 0.0;
+        }
+        if state.buffered_jump > 0.0 && state.coyote > 0.0 {
+            state.velocity.y = jump.impulse();
+            state.jumped = true;
+            state.cuttable = true;
+            state.buffered_jump = 0.0;
+            state.coyote = 0.0;
+        } else if state.buffered_jump > 0.0 && state.wall != 0.0 && !was_grounded {
+            // Off the wall, whatever the button does next.
+            state.velocity = Vec2::new(-state.wall * wall.jump_velocity.x, wall.jump_velocity.y);
+            state.facing = -state.wall;
+            state.jumped = true;
+            state.wall_jumped = true;
+            state.cuttable = false;
+            state.buffered_jump = 0.0;
+            state.wall_jump_lock = wall.jump_lock;
+        }
+        if state.velocity.y <= 0.0 {
+            state.cuttable = false;
+        } else if state.cuttable && !input.jump_held {
+            state.velocity.y *= jump.release_cut;
+            state.cuttable = false;
+        }
+        state.velocity.y = if state.dashing && !state.jumped {
+            0.0
+        } else {
+            (state.velocity.y - jump.gravity() * dt).max(-controller.max_fall_speed)
+        };
+
+        state.wall_sliding = !was_grounded
+            && state.wall != 0.0
+            && input.move_x * state.wall > 0.0
+            && state.velocity.y < 0.0;
+        if state.wall_sliding {
+            state.velocity.y = state.velocity.y.max(-wall.slide_speed);
+        }
+
+        let caster = Caster {
+            context: &rapier_context,
+            entity,
+            collider,
+            platforms: &platforms,
+            passing: drop.map_or(false, |drop| drop.is_dropping()) || state.velocity.y > 0.0,
+            min_ground_y,
+        };
+        let mut remaining = state.velocity * dt;
+        // Walking down a slope: follow the ground instead of stepping into the air.
+        if was_grounded && !state.jumped {
+            let normal = state.ground_normal;
+            remaining = Vec2::new(remaining.x, -normal.x / normal.y * remaining.x);
+        }
+
+        state.grounded = false;
+        state.ground = None;
+        state.ground_normal = Vec2::Y;
+        state.hit_ceiling = false;
+        state.hit_wall = false;
+
+        for _ in 0..MAX_SLIDES {
+            if remaining.length_squared() < 1e-6 {
+                break;
+            }
+            let (hit, toi, normal) = match caster.cast(position, remaining) {
+                Some(v) => v,
+                None => {
+                    position += remaining;
+                    break;
+                }
+            };
+
+            // Up to the obstacle, keeping the skin.
+            let length = remaining.length();
+            let travel = (toi.toi * length - controller.skin).max(0.0);
+            position += remaining / length * travel;
+            remaining *= 1.0 - travel / length;
+
+            if normal.y >= min_ground_y {
+                state.grounded = true;
+                state.ground = Some(hit);
+                state.ground_normal = normal;
+                state.velocity.y = state.velocity.y.max(0.0);
+            } else if normal.y <= -min_ground_y {
+                // Bump the head: stop rising.
+                state.hit_ceiling = true;
+                state.velocity.y = state.velocity.y.min(0.0);
+            } else {
+                let stepped = if was_grounded && remaining.x != 0.0 {
+                    step_up(&caster, controller, position, remaining.x)
+                } else {
+                    None
+                };
+                if let Some(stepped) = stepped {
+                    position = stepped;
+                    remaining = Vec2::ZERO;
+                    continue;
+                }
+                state.hit_wall = true;
+                if state.velocity.x * normal.x < 0.0 {
+                    state.velocity.x = 0.0;
+                }
+            }
+            // Slide along the surface with what's left.
+            remaining -= normal * remaining.dot(normal);
+        }
+
+        // Stay on the ground when it drops away under the feet.
+        let probe = if was_grounded && !state.jumped {
+            controller.snap_distance
+        } else {
+            controller.skin * 2.0
+        };
+        if !state.grounded && state.velocity.y <= 0.0 {
+            if let Some((hit, toi, normal)) = caster.cast(position, Vec2::new(0.0, -probe)) {
+                if normal.y >= min_ground_y {
+                    position.y -= (toi.toi * probe - controller.skin).max(0.0);
+                    state.grounded = true;
+                    state.ground = Some(hit);
+                    state.ground_normal = normal;
+                    state.velocity.y = 0.0;
+                }
+            }
+        }
+
+        // Walls on either side, and ledges to grab while falling against them.
+        state.wall = 0.0;
+        if !state.grounded {
+            for side in [-1.0, 1.0] {
+                let hit = caster.cast(position, Vec2::new(side * wall.probe, 0.0));
+                if matches!(hit, Some((_, _, normal)) if normal.y.abs() < min_ground_y) {
+                    state.wall = side;
+                }
+            }
+        }
+        let grabbing = state.wall != 0.0
+            && input.move_x * state.wall > 0.0
+            && state.velocity.y <= 0.0
+            && input.move_y >= -0.5;
+        if grabbing {
+            if let Some(ledge) = find_ledge(&caster, controller, position, state.wall) {
+                state.hanging = true;
+                state.ledge = ledge;
+                state.velocity = Vec2::ZERO;
+                state.wall_sliding = false;
+                position.y = ledge.y - wall.ledge_hang;
+            }
+        }
+
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+/// Where the character would stand on top of the wall on side `side`, when
+/// that top is within reach above it.
+fn find_ledge(
+    caster: &Caster,
+    controller: &CharacterController,
+    position: Vec2,
+    side: f32,
+) -> Option<Vec2> {
+    let up = Vec2::new(0.0, controller.wall.ledge_reach);
+    if caster.cast(position, up).is_some() {
+        return None;
+    }
+    // The wall must stop below the raised character...
+    let raised = position + up;
+    let over = Vec2::new(side * controller.wall.ledge_inset, 0.0);
+    if caster.cast(raised, over).is_some() {
+        return None;
+    }
+    // ...with ground to stand on past it.
+    let (_, toi, normal) = caster.cast(raised + over, -up)?;
+    if normal.y < caster.min_ground_y || toi.toi <= 0.0 {
+        return None;
+    }
+    Some(raised + over - up * toi.toi + Vec2::new(0.0, controller.skin))
+}
+
+/// Position after climbing a ledge no higher than the step height in front
+/// of the character, if there is room to stand on it.
+fn step_up(
+    caster: &Caster,
+    controller: &CharacterController,
+    position: Vec2,
+    motion_x: f32,
+) -> Option<Vec2> {
+    let up = Vec2::new(0.0, controller.step_height);
+    if caster.cast(position, up).is_some() {
+        return None;
+    }
+    // At least the skin forward, or the ledge is never reached.
+    let forward = Vec2::new(
+        motion_x.signum() * motion_x.abs().max(controller.skin * 2.0),
+        0.0,
+    );
+    let raised = position + up;
+    if caster.cast(raised, forward).is_some() {
+        return None;
+    }
+    let ahead = raised + forward;
+    let (_, toi, normal) = caster.cast(ahead, -up)?;
+    if normal.y < caster.min_ground_y || toi.toi <= 0.0 {
+        return None;
+    }
+    Some(ahead - up * toi.toi + Vec2::new(0.0, controller.skin))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bevy::{hierarchy::HierarchyPlugin, transform::TransformPlugin};
+    use bevy_rapier2d::prelude::{NoUserData, RapierPhysicsPlugin};
+
+    use super::*;
+
+    const HALF_HEIGHT: f32 = 16.0;
+    const FLOOR_TOP: f32 = 10.0;
+    const FRAME: f32 = 1.0 / 60.0;
+
+    /// A floor, and a character with its feet `height` above it.
+    fn spawn_app(height: f32) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0))
+            .add_plugin(CharacterControllerPlugin);
+        let mut time = Time::default();
+        time.update_with_instant(Instant::now());
+        app.insert_resource(time);
+
+        app.world
+            .spawn()
+            .insert(RigidBody::Fixed)
+            .insert(Collider::cuboid(200.0, FLOOR_TOP))
+            .insert_bundle(TransformBundle::default());
+        let character = app
+            .world
+            .spawn()
+            .insert_bundle(CharacterControllerBundle::default())
+            .insert(Collider::cuboid(8.0, HALF_HEIGHT))
+            .insert_bundle(TransformBundle::from(Transform::from_xyz(
+                0.0,
+                FLOOR_TOP + HALF_HEIGHT + height,
+                0.0,
+            )))
+            .id();
+        (app, character)
+    }
+
+    fn step(app: &mut App, frames: usize) {
+        for _ in 0..frames {
+            let mut time = app.world.resource_mut::<Time>();
+            let now = time.last_update().unwrap() + Duration::from_secs_f32(FRAME);
+            time.update_with_instant(now);
+            app.update();
+        }
+    }
+
+    fn state(app: &App, character: Entity) -> &CharacterState {
+        app.world.get::<CharacterState>(character).unwrap()
+    }
+
+    fn input(app: &mut App, character: Entity) -> Mut<CharacterInput> {
+        app.world.get_mut::<CharacterInput>(character).unwrap()
+    }
+
+    /// Height of the character's feet above the floor.
+    fn feet(app: &App, character: Entity) -> f32 {
+        let y = app.world.get::<Transform>(character).unwrap().translation.y;
+        y - HALF_HEIGHT - FLOOR_TOP
+    }
+
+    /// Steps one frame with the jump button pressed or released.
+    fn jump(app: &mut App, character: Entity, pressed: bool, held: bool) {
+        {
+            let mut input = input(app, character);
+            input.jump = pressed;
+            input.jump_held = held;
+        }
+        step(app, 1);
+    }
+
+    #[test]
+    fn character_falls_onto_the_floor() {
+        let (mut app, character) = spawn_app(34.0);
+        step(&mut app, 60);
+
+        let state = state(&app, character);
+        assert!(state.grounded);
+        assert!(state.ground_normal.y > 0.99);
+        assert_eq!(state.velocity.y, 0.0);
+        // The skin is kept between the feet and the floor.
+        let feet = feet(&app, character);
+        assert!((feet - 0.5).abs() < 0.5, "stands at {}", feet);
+    }
+
+    #[test]
+    fn jump_model_reaches_its_apex() {
+        let models = [
+            JumpModel::default(),
+            JumpModel {
+                apex_height: 64.0,
+                time_to_apex: 0.5,
+                ..default()
+            },
+        ];
+        for jump in models {
+            let (gravity, impulse) = (jump.gravity(), jump.impulse());
+            // Rising at `impulse` against `gravity` until the speed is spent.
+            let time = impulse / gravity;
+            let height = impulse * time - gravity * time * time / 2.0;
+            assert!((time - jump.time_to_apex).abs() < 1e-5);
+            assert!((height - jump.apex_height).abs() < 1e-3);
+        }
+    }
+
+    /// Height reached by a jump whose button is released after `held` frames.
+    fn jump_height(held: usize) -> f32 {
+        let (mut app, character) = spawn_app(1.0);
+        step(&mut app, 10);
+        assert!(state(&app, character).grounded);
+        let start = feet(&app, character);
+
+        jump(&mut app, character, true, true);
+        assert!(state(&app, character).jumped);
+        let mut apex = feet(&app, character);
+        for frame in 1..60 {
+            jump(&mut app, character, false, frame < held);
+            apex = apex.max(feet(&app, character));
+        }
+        apex - start
+    }
+
+    #[test]
+    fn holding_jump_reaches_the_apex() {
+        let height = jump_height(60);
+        // Frame steps lose a few pixels on the way up.
+        let apex = JumpModel::default().apex_height;
+        assert!(height > apex * 0.9 && height <= apex, "{}", height);
+    }
+
+    #[test]
+    fn releasing_jump_cuts_it() {
+        let model = JumpModel::default();
+        let (mut app, character) = spawn_app(1.0);
+        step(&mut app, 10);
+        jump(&mut app, character, true, true);
+        jump(&mut app, character, false, true);
+        let rising = state(&app, character).velocity.y;
+        assert!(rising > 0.0);
+
+        jump(&mut app, character, false, false);
+        let cut = rising * model.release_cut - model.gravity() * FRAME;
+        assert!((state(&app, character).velocity.y - cut).abs() < 1e-2);
+        // Only once: holding again changes nothing.
+        let before = state(&app, character).velocity.y;
+        jump(&mut app, character, false, true);
+        let after = before - model.gravity() * FRAME;
+        assert!((state(&app, character).velocity.y - after).abs() < 1e-2);
+
+        assert!(jump_height(3) < jump_height(60) * 0.6);
+    }
+
+    /// Whether jumping `frames` frames after walking off a ledge still jumps.
+    fn coyote_jump(frames: usize) -> bool {
+        let (mut app, character) = spawn_app(200.0);
+        app.world
+            .get_mut::<CharacterState>(character)
+            .unwrap()
+            .grounded = true;
+        step(&mut app, frames);
+        jump(&mut app, character, true, true);
+        state(&app, character).jumped
+    }
+
+    #[test]
+    fn coyote_time_allows_late_jumps() {
+        // 0.05 s of the 0.1 s allowed left.
+        assert!(coyote_jump(3));
+        assert!(!coyote_jump(9));
+    }
+
+    /// Whether a jump pressed `frames` frames before landing fires on landing.
+    fn buffered_jump(frames: usize) -> bool {
+        let height = 80.0;
+        let (mut app, character) = spawn_app(height);
+        let mut landing = 0;
+        while !state(&app, character).grounded {
+            step(&mut app, 1);
+            landing += 1;
+        }
+        assert!(landing > frames);
+
+        let (mut app, character) = spawn_app(height);
+        let mut jumped = false;
+        for frame in 1..=landing + 3 {
+            jump(&mut app, character, frame + frames == landing, true);
+            jumped |= state(&app, character).jumped;
+        }
+        jumped
+    }
+
+    #[test]
+    fn jumps_pressed_before_landing_are_buffered() {
+        // Fires the frame after landing, 0.067 s into the 0.12 s buffer.
+        assert!(buffered_jump(3));
+        assert!(!buffered_jump(12));
+    }
+}
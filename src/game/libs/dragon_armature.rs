@@ -0,0 +1,456 @@
+use bevy::{
+    math::{Affine2, Mat2},
+    prelude::*,
+};
+
+use super::{
+    dragon_loader::AnimationData,
+    dragon_models::{self, Armature, ArmatureSlot, Bone, Display, Tween},
+    dragon_player::DragonAnimationPlayer,
+};
+
+/// Z distance between two slots of the same armature.
+const SLOT_Z_STEP: f32 = 0.001;
+
+/// Renders a DragonBones clip as a real skeleton: every slot of the armature
+/// becomes a child sprite that is re-posed from the bone timelines each frame.
+/// The clip itself is driven by the entity's [`DragonAnimationPlayer`].
+#[derive(Component, Default)]
+pub struct DragonArmature {
+    armature: Option<String>,
+    slots: Vec<Entity>,
+}
+
+#[derive(Default, Bundle)]
+pub struct DragonArmatureBundle {
+    pub armature: DragonArmature,
+    pub player: DragonAnimationPlayer,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub computed_visibility: ComputedVisibility,
+}
+
+#[derive(Component)]
+pub struct DragonSlot {
+    pub name: String,
+    index: usize,
+}
+
+/// (Re)spawns the slot children whenever the current clip lives in another armature.
+pub(super) fn spawn_armature_slots(
+    mut commands: Commands,
+    datas: Res<Assets<AnimationData>>,
+    mut query: Query<(Entity, &DragonAnimationPlayer, &mut DragonArmature)>,
+) {
+    for (entity, player, mut dragon) in &mut query {
+        let data = match datas.get(&player.data) {
+            Some(v) => v,
+            None => continue,
+        };
+        let armature = match player.clip(data) {
+            Some((armature, _)) => armature,
+            None => continue,
+        };
+        if dragon.armature.as_deref() == Some(armature.name.as_str()) {
+            continue;
+        }
+
+        for slot in dragon.slots.drain(..) {
+            commands.entity(slot).despawn_recursive();
+        }
+
+        let slots: Vec<Entity> = armature
+            .slot
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| {
+                commands
+                    .spawn_bundle(SpriteSheetBundle {
+                        texture_atlas: data.atlas.clone(),
+                        visibility: Visibility { is_visible: false },
+                        ..default()
+                    })
+                    .insert(DragonSlot {
+                        name: slot.name.clone(),
+                        index,
+                    })
+                    .id()
+            })
+            .collect();
+
+        commands.entity(entity).push_children(&slots);
+        dragon.slots = slots;
+        dragon.armature = Some(armature.name.clone());
+    }
+}
+
+pub(super) fn pose_armatures(
+    datas: Res<Assets<AnimationData>>,
+    armatures: Query<(&DragonAnimationPlayer, &DragonArmature)>,
+    mut slots: Query<(
+        &DragonSlot,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+        &mut Visibility,
+    )>,
+) {
+    for (player, dragon) in &armatures {
+        let data = match datas.get(&player.data) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (armature, animation) = match player.clip(data) {
+            Some(v) => v,
+            None => continue,
+        };
+        let frame = player.frame();
+
+        let bones = pose_bones(armature, animation, frame);
+
+        for slot_entity in dragon.slots.iter() {
+            let (slot, mut transform, mut sprite, mut visibility) =
+                match slots.get_mut(*slot_entity) {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+            let armature_slot = match armature.slot.get(slot.index) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let display = match slot_display(armature, animation, armature_slot, frame) {
+                Some(v) => v,
+                None => {
+                    visibility.is_visible = false;
+                    continue;
+                }
+            };
+            let texture = display.path.as_deref().unwrap_or(&display.name);
+            let index = match data.map.get(texture) {
+                Some(v) => *v,
+                None => {
+                    visibility.is_visible = false;
+                    continue;
+                }
+            };
+
+            let bone = armature
+                .bone
+                .iter()
+                .position(|bone| bone.name == armature_slot.parent)
+                .map(|i| bones[i])
+                .unwrap_or(Affine2::IDENTITY);
+            let local = display
+                .transform
+                .as_ref()
+                .map(local_affine)
+                .unwrap_or(Affine2::IDENTITY);
+            let global = bone * local * Affine2::from_translation(data.texture_offset(texture));
+
+            *transform = to_bevy_transform(&global, slot.index as f32 * SLOT_Z_STEP);
+            sprite.index = index as usize;
+            visibility.is_visible = true;
+        }
+    }
+}
+
+/// Computes the armature-space matrix of every bone (same order as `armature.bone`).
+pub fn pose_bones(
+    armature: &Armature,
+    animation: &dragon_models::Animation,
+    frame: f32,
+) -> Vec<Affine2> {
+    let mut globals: Vec<Affine2> = Vec::with_capacity(armature.bone.len());
+
+    for bone in armature.bone.iter() {
+        let mut local = bone.transform.clone();
+
+        if let Some(timeline) = animation.bone.iter().find(|it| it.name == bone.name) {
+            if let Some((from, to, t)) = locate(&timeline.translate_frame, |f| &f.tween, frame) {
+                let (x, y) = match to {
+                    Some(to) => (from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t),
+                    None => (from.x, from.y),
+                };
+                local.x += x;
+                local.y += y;
+            }
+            if let Some((from, to, t)) = locate(&timeline.rotate_frame, |f| &f.tween, frame) {
+                let rotate = match to {
+                    Some(to) => {
+                        from.rotate + rotate_delta(from.rotate, to.rotate, from.clockwise) * t
+                    }
+                    None => from.rotate,
+                };
+                local.sk_x += rotate;
+                local.sk_y += rotate;
+            }
+            if let Some((from, to, t)) = locate(&timeline.scale_frame, |f| &f.tween, frame) {
+                let (x, y) = match to {
+                    Some(to) => (from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t),
+                    None => (from.x, from.y),
+                };
+                local.sc_x *= x;
+                local.sc_y *= y;
+            }
+        }
+
+        let parent = bone
+            .parent
+            .as_ref()
+            .and_then(|name| armature.bone.iter().position(|it| &it.name == name))
+            .and_then(|i| globals.get(i))
+            .copied();
+
+        let local = local_affine(&local);
+        globals.push(match parent {
+            Some(parent) => inherit(&parent, &local, bone),
+            None => local,
+        });
+    }
+
+    globals
+}
+
+/// Index into the slot's skin displays shown at `frame`, negative when the slot is empty.
+pub fn slot_display_index(
+    animation: &dragon_models::Animation,
+    slot: &ArmatureSlot,
+    frame: f32,
+) -> i32 {
+    animation
+        .slot
+        .iter()
+        .find(|it| it.name == slot.name)
+        .and_then(|timeline| {
+            let mut start = 0.0;
+            let last = timeline.display_frame.len().checked_sub(1)?;
+            timeline
+                .display_frame
+                .iter()
+                .enumerate()
+                .find(|(i, it)| {
+                    start += it.duration as f32;
+                    frame < start || *i == last
+                })
+                .map(|(_, it)| it.value)
+        })
+        .unwrap_or(slot.display_index)
+}
+
+/// Every display the default skin provides for `slot`.
+pub fn slot_displays<'a>(armature: &'a Armature, slot: &ArmatureSlot) -> Option<&'a [Display]> {
+    armature
+        .skin
+        .first()?
+        .slot
+        .iter()
+        .find(|it| it.name == slot.name)
+        .map(|it| it.display.as_slice())
+}
+
+/// Returns the display shown by a slot at `frame`, `None` when the slot is empty.
+pub fn slot_display<'a>(
+    armature: &'a Armature,
+    animation: &dragon_models::Animation,
+    slot: &ArmatureSlot,
+    frame: f32,
+) -> Option<&'a Display> {
+    let index = slot_display_index(animation, slot, frame);
+    if index < 0 {
+        return None;
+    }
+    slot_displays(armature, slot)?.get(index as usize)
+}
+
+/// Finds the keyframe playing at `frame`, the keyframe it tweens towards and the eased progress.
+fn locate<T>(
+    frames: &[T],
+    tween: impl Fn(&T) -> &Tween,
+    frame: f32,
+) -> Option<(&T, Option<&T>, f32)> {
+    let mut start = 0.0;
+    for (i, it) in frames.iter().enumerate() {
+        let duration = tween(it).duration as f32;
+        if frame < start + duration || i + 1 == frames.len() {
+            let next = frames.get(i + 1);
+            let progress = if duration > 0.0 && next.is_some() {
+                ease(tween(it), ((frame - start) / duration).clamp(0.0, 1.0))
+            } else {
+                0.0
+            };
+            return Some((it, next, progress));
+        }
+        start += duration;
+    }
+    None
+}
+
+fn ease(tween: &Tween, progress: f32) -> f32 {
+    if tween.curve.len() >= 2 {
+        return bezier_curve(&tween.curve, progress);
+    }
+    match tween.tween_easing {
+        None => 0.0,
+        Some(easing) if easing == 0.0 => progress,
+        Some(easing) => {
+            let (value, strength) = if easing < 0.0 {
+                (progress * progress, -easing)
+            } else if easing <= 1.0 {
+                (1.0 - (1.0 - progress) * (1.0 - progress), easing)
+            } else {
+                (
+                    0.5 * (1.0 - (progress * std::f32::consts::PI).cos()),
+                    easing - 1.0,
+                )
+            };
+            progress + (value - progress) * strength.min(1.0)
+        }
+    }
+}
+
+/// Evaluates a DragonBones `curve` (control points between (0, 0) and (1, 1)) at `x`.
+fn bezier_curve(curve: &[f32], x: f32) -> f32 {
+    let mut points = vec![Vec2::ZERO];
+    points.extend(curve.chunks_exact(2).map(|p| Vec2::new(p[0], p[1])));
+    points.push(Vec2::ONE);
+
+    let at = |s: f32| {
+        let mut p = points.clone();
+        for k in (1..p.len()).rev() {
+            for i in 0..k {
+                p[i] = p[i].lerp(p[i + 1], s);
+            }
+        }
+        p[0]
+    };
+
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..16 {
+        let mid = (lo + hi) / 2.0;
+        if at(mid).x < x {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    at((lo + hi) / 2.0).y
+}
+
+fn rotate_delta(from: f32, to: f32, clockwise: i32) -> f32 {
+    let mut delta = to - from;
+    if clockwise == 0 {
+        delta = (delta + 180.0).rem_euclid(360.0) - 180.0;
+    } else if clockwise > 0 {
+        delta = delta.rem_euclid(360.0) + 360.0 * (clockwise - 1) as f32;
+    } else {
+        delta = delta.rem_euclid(360.0) - 360.0 * (-clockwise) as f32;
+    }
+    delta
+}
+
+pub(super) fn local_affine(transform: &dragon_models::Transform) -> Affine2 {
+    let (sin_x, cos_x) = transform.sk_x.to_radians().sin_cos();
+    let (sin_y, cos_y) = transform.sk_y.to_radians().sin_cos();
+    Affine2::from_mat2_translation(
+        Mat2::from_cols(
+            Vec2::new(cos_y, sin_y) * transform.sc_x,
+            Vec2::new(-sin_x, cos_x) * transform.sc_y,
+        ),
+        Vec2::new(transform.x, transform.y),
+    )
+}
+
+/// Applies a parent bone to a child, honouring the bone's inherit flags.
+fn inherit(parent: &Affine2, local: &Affine2, bone: &Bone) -> Affine2 {
+    if bone.inherit_translation && bone.inherit_rotation && bone.inherit_scale {
+        return *parent * *local;
+    }
+
+    let translation = if bone.inherit_translation {
+        parent.transform_point2(local.translation)
+    } else {
+        parent.translation + local.translation
+    };
+
+    let mut matrix = local.matrix2;
+    if bone.inherit_scale {
+        matrix = Mat2::from_diagonal(Vec2::new(
+            parent.matrix2.x_axis.length(),
+            parent.matrix2.y_axis.length(),
+        )) * matrix;
+    }
+    if bone.inherit_rotation {
+        matrix = Mat2::from_angle(parent.matrix2.x_axis.y.atan2(parent.matrix2.x_axis.x)) * matrix;
+    }
+
+    Affine2::from_mat2_translation(matrix, translation)
+}
+
+/// Converts a DragonBones (y-down) matrix into a bevy transform; skew is dropped.
+fn to_bevy_transform(affine: &Affine2, z: f32) -> Transform {
+    let x_axis = Vec2::new(affine.matrix2.x_axis.x, -affine.matrix2.x_axis.y);
+    let y_axis = Vec2::new(-affine.matrix2.y_axis.x, affine.matrix2.y_axis.y);
+    let scale_x = x_axis.length();
+    let scale_y = if scale_x > 0.0 {
+        x_axis.perp_dot(y_axis) / scale_x
+    } else {
+        0.0
+    };
+
+    Transform {
+        translation: Vec3::new(affine.translation.x, -affine.translation.y, z),
+        rotation: Quat::from_rotation_z(x_axis.y.atan2(x_axis.x)),
+        scale: Vec3::new(scale_x, scale_y, 1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tween(tween_easing: Option<f32>, curve: &[f32]) -> Tween {
+        Tween {
+            tween_easing,
+            curve: curve.to_vec(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn ease_follows_tween_easing() {
+        assert_eq!(ease(&tween(None, &[]), 0.5), 0.0);
+        assert_eq!(ease(&tween(Some(0.0), &[]), 0.3), 0.3);
+        // Ease in, ease out, then ease in and out.
+        assert!((ease(&tween(Some(-1.0), &[]), 0.5) - 0.25).abs() < 1e-6);
+        assert!((ease(&tween(Some(1.0), &[]), 0.5) - 0.75).abs() < 1e-6);
+        assert!((ease(&tween(Some(2.0), &[]), 0.5) - 0.5).abs() < 1e-6);
+        assert!(ease(&tween(Some(2.0), &[]), 0.25) < 0.25);
+        // Half strength blends with linear.
+        assert!((ease(&tween(Some(-0.5), &[]), 0.5) - 0.375).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ease_prefers_curve() {
+        let linear = tween(Some(-1.0), &[0.25, 0.25, 0.75, 0.75]);
+        for x in [0.0, 0.2, 0.5, 0.9, 1.0] {
+            assert!((ease(&linear, x) - x).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn bezier_curve_spans_unit_square() {
+        let curve = [0.5, 0.0, 1.0, 1.0];
+        assert!(bezier_curve(&curve, 0.0).abs() < 1e-3);
+        assert!((bezier_curve(&curve, 1.0) - 1.0).abs() < 1e-3);
+
+        let mut last = 0.0;
+        for i in 1..=10 {
+            let y = bezier_curve(&curve, i as f32 / 10.0);
+            assert!(y >= last);
+            last = y;
+        }
+        // Control points under the diagonal ease in.
+        assert!(bezier_curve(&curve, 0.5) < 0.5);
+    }
+}
@@ -0,0 +1,425 @@
+//! Hitboxes that follow the frames of DragonBones clips: temporary sensors
+//! spawned while a clip shows them, reporting what they touch as [`HitEvent`]s.
+
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    math::Affine2,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::{ActiveCollisionTypes, Collider, RapierContext, Sensor};
+use serde::Deserialize;
+
+use super::{
+    dragon_armature::{local_affine, pose_bones, slot_display},
+    dragon_loader::AnimationData,
+    dragon_models::Display,
+    dragon_player::{DragonAnimationPlayer, DragonAnimationSystem},
+};
+
+#[derive(Default)]
+pub struct DragonHitboxPlugin;
+
+impl Plugin for DragonHitboxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<HitboxSet>()
+            .init_asset_loader::<HitboxSetLoader>()
+            .add_event::<HitEvent>()
+            .add_system(update_hitboxes.after(DragonAnimationSystem::Advance))
+            .add_system(detect_hits);
+    }
+}
+
+#[derive(Default)]
+pub struct HitboxSetLoader;
+
+impl AssetLoader for HitboxSetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let set: HitboxSet = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(set));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hitboxes.ron"]
+    }
+}
+
+/// Hitboxes of clips that don't draw them as DragonBones bounding boxes,
+/// keyed by clip name. Loaded from `*.hitboxes.ron` files.
+#[derive(Debug, Clone, Default, Deserialize, TypeUuid)]
+#[uuid = "3f0d7a52-8c1e-4d43-9a3b-7f4e2d6c1b90"]
+pub struct HitboxSet {
+    pub clips: HashMap<String, Vec<HitboxFrames>>,
+}
+
+/// A box shown from frame `first` to frame `last` included, in DragonBones
+/// space (y down, centred on the character).
+#[derive(Debug, Clone, Deserialize)]
+pub struct HitboxFrames {
+    pub first: u32,
+    pub last: u32,
+    pub center: Vec2,
+    pub size: Vec2,
+    #[serde(default = "default_damage")]
+    pub damage: f32,
+}
+
+fn default_damage() -> f32 {
+    1.0
+}
+
+/// Gives an animated entity the hitboxes of its clips: the `boundingBox`
+/// displays of its armatures, plus the boxes of `sidecar` when set.
+#[derive(Component)]
+pub struct DragonHitboxes {
+    pub sidecar: Option<Handle<HitboxSet>>,
+    /// Damage of bounding boxes, which carry none.
+    pub damage: f32,
+}
+
+impl Default for DragonHitboxes {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl DragonHitboxes {
+    pub fn new(sidecar: Option<Handle<HitboxSet>>) -> Self {
+        Self {
+            sidecar,
+            damage: default_damage(),
+        }
+    }
+}
+
+/// What hitboxes can hit. Hit events name the entity carrying it.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct Hurtbox;
+
+/// A hitbox sensor, child of its attacker.
+#[derive(Component)]
+pub struct Hitbox {
+    pub attacker: Entity,
+    pub damage: f32,
+    /// Clip and box it was spawned for.
+    key: String,
+    /// Hit already: a box hits each victim once while it shows.
+    hits: Vec<Entity>,
+    /// Whether its collider is mirrored for a sprite facing left.
+    flipped: bool,
+}
+
+/// Sent the first frame a hitbox touches a [`Hurtbox`].
+#[derive(Debug, Clone)]
+pub struct HitEvent {
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub hitbox: Entity,
+    pub damage: f32,
+}
+
+/// A box to show this frame.
+struct ShownHitbox {
+    key: String,
+    collider: Collider,
+    transform: Transform,
+    damage: f32,
+}
+
+/// The bounding boxes an armature shows at the player's frame, with their
+/// polygons mirrored when `flip_x` is set.
+fn bounding_boxes(
+    data: &AnimationData,
+    player: &DragonAnimationPlayer,
+    damage: f32,
+    flip_x: bool,
+) -> Vec<ShownHitbox> {
+    let (armature, animation) = match player.clip(data) {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+    let frame = player.frame();
+    let mut bones: Option<Vec<Affine2>> = None;
+    let mut shown = Vec::new();
+
+    for (index, slot) in armature.slot.iter().enumerate() {
+        let display = match slot_display(armature, animation, slot, frame) {
+            Some(v) if v.type_field == "boundingBox" => v,
+            _ => continue,
+        };
+        let collider = match bounding_box_collider(display, flip_x) {
+            Some(v) => v,
+            None => continue,
+        };
+        let bones = bones.get_or_insert_with(|| pose_bones(armature, animation, frame));
+        let bone = armature
+            .bone
+            .iter()
+            .position(|bone| bone.name == slot.parent)
+            .map(|i| bones[i])
+            .unwrap_or(Affine2::IDENTITY);
+        let local = display
+            .transform
+            .as_ref()
+            .map(local_affine)
+            .unwrap_or(Affine2::IDENTITY);
+        let global = bone * local;
+
+        // DragonBones is y down; only the position and rotation are kept.
+        let x_axis = Vec2::new(global.matrix2.x_axis.x, -global.matrix2.x_axis.y);
+        shown.push(ShownHitbox {
+            key: format!("{}/{}", animation.name, index),
+            collider,
+            transform: Transform::from_xyz(global.translation.x, -global.translation.y, 0.0)
+                .with_rotation(Quat::from_rotation_z(x_axis.y.atan2(x_axis.x))),
+            damage,
+        });
+    }
+    shown
+}
+
+/// Collider of a `boundingBox` display. Rectangles and circles look the same
+/// mirrored, polygons are mirrored along x when `flip_x` is set.
+fn bounding_box_collider(display: &Display, flip_x: bool) -> Option<Collider> {
+    match display.sub_type.as_deref().unwrap_or("rectangle") {
+        "rectangle" => Some(Collider::cuboid(display.width / 2.0, display.height / 2.0)),
+        "ellipse" => Some(Collider::ball(display.width.min(display.height) / 2.0)),
+        "polygon" => {
+            let x = if flip_x { -1.0 } else { 1.0 };
+            let points: Vec<Vec2> = display
+                .vertices
+                .chunks_exact(2)
+                .map(|it| Vec2::new(x * it[0], -it[1]))
+                .collect();
+            Collider::convex_hull(&points)
+        }
+        _ => None,
+    }
+}
+
+/// The sidecar boxes of the current clip shown at the player's frame.
+fn sidecar_boxes(set: &HitboxSet, player: &DragonAnimationPlayer) -> Vec<ShownHitbox> {
+    let frames = match set.clips.get(player.animation()) {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+    let frame = player.frame() as u32;
+    frames
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| (it.first..=it.last).contains(&frame))
+        .map(|(index, it)| ShownHitbox {
+            key: format!("{}#{}", player.animation(), index),
+            collider: Collider::cuboid(it.size.x / 2.0, it.size.y / 2.0),
+            transform: Transform::from_xyz(it.center.x, -it.center.y, 0.0),
+            damage: it.damage,
+        })
+        .collect()
+}
+
+/// Spawns the hitboxes shown by each clip's current frame, moves the ones
+/// still shown and despawns the others.
+fn update_hitboxes(
+    mut commands: Commands,
+    datas: Res<Assets<AnimationData>>,
+    sets: Res<Assets<HitboxSet>>,
+    attackers: Query<(
+        Entity,
+        &DragonAnimationPlayer,
+        &DragonHitboxes,
+        Option<&TextureAtlasSprite>,
+        Option<&Children>,
+    )>,
+    mut hitboxes: Query<(&mut Hitbox, &mut Transform, &mut Collider)>,
+) {
+    for (entity, player, settings, sprite, children) in &attackers {
+        // Sheet sprites face left by flipping; so do their boxes.
+        let flip_x = sprite.map_or(false, |it| it.flip_x);
+        let mut shown = match datas.get(&player.data) {
+            Some(data) => bounding_boxes(data, player, settings.damage, flip_x),
+            None => Vec::new(),
+        };
+        if let Some(set) = settings.sidecar.as_ref().and_then(|it| sets.get(it)) {
+            shown.extend(sidecar_boxes(set, player));
+        }
+        if flip_x {
+            for it in shown.iter_mut() {
+                it.transform.translation.x = -it.transform.translation.x;
+                it.transform.rotation = it.transform.rotation.inverse();
+            }
+        }
+
+        for child in children.into_iter().flat_map(|it| it.iter()) {
+            let (mut hitbox, mut transform, mut collider) = match hitboxes.get_mut(*child) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            match shown.iter().position(|it| it.key == hitbox.key) {
+                Some(index) => {
+                    let it = shown.swap_remove(index);
+                    *transform = it.transform;
+                    // Turning around mirrors the shape, not only its placement.
+                    if hitbox.flipped != flip_x {
+                        hitbox.flipped = flip_x;
+                        *collider = it.collider;
+                    }
+                }
+                None => commands.entity(*child).despawn_recursive(),
+            }
+        }
+
+        for it in shown {
+            let hitbox = commands
+                .spawn_bundle(TransformBundle::from_transform(it.transform))
+                .insert(Hitbox {
+                    attacker: entity,
+                    damage: it.damage,
+                    key: it.key,
+                    hits: Vec::new(),
+                    flipped: flip_x,
+                })
+                .insert(it.collider)
+                .insert(Sensor)
+                // Sensors of kinematic characters only meet other bodies when asked to.
+                .insert(ActiveCollisionTypes::all())
+                .id();
+            commands.entity(entity).add_child(hitbox);
+        }
+    }
+}
+
+fn detect_hits(
+    rapier_context: Res<RapierContext>,
+    mut events: EventWriter<HitEvent>,
+    mut hitboxes: Query<(Entity, &mut Hitbox)>,
+    hurtboxes: Query<(), With<Hurtbox>>,
+) {
+    for (entity, mut hitbox) in &mut hitboxes {
+        for (collider1, collider2, intersecting) in rapier_context.intersections_with(entity) {
+            let victim = if collider1 == entity {
+                collider2
+            } else {
+                collider1
+            };
+            if !intersecting
+                || victim == hitbox.attacker
+                || !hurtboxes.contains(victim)
+                || hitbox.hits.contains(&victim)
+            {
+                continue;
+            }
+            hitbox.hits.push(victim);
+            events.send(HitEvent {
+                attacker: hitbox.attacker,
+                victim,
+                hitbox: entity,
+                damage: hitbox.damage,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bevy::ecs::event::Events;
+    use bevy_rapier2d::prelude::{NoUserData, RapierPhysicsPlugin};
+
+    use super::*;
+
+    fn spawn_box(app: &mut App, x: f32) -> Entity {
+        let transform = Transform::from_xyz(x, 0.0, 0.0);
+        app.world
+            .spawn()
+            .insert(Collider::cuboid(10.0, 10.0))
+            .insert(ActiveCollisionTypes::all())
+            .insert(transform)
+            .insert(GlobalTransform::from(transform))
+            .id()
+    }
+
+    fn spawn_hitbox(app: &mut App, x: f32, attacker: Entity) -> Entity {
+        let hitbox = spawn_box(app, x);
+        app.world.entity_mut(hitbox).insert(Sensor).insert(Hitbox {
+            attacker,
+            damage: 2.0,
+            key: format!("attack#{}", x),
+            hits: Vec::new(),
+            flipped: false,
+        });
+        hitbox
+    }
+
+    #[test]
+    fn hitboxes_hit_each_hurtbox_once() {
+        let mut app = App::new();
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0))
+            .add_event::<HitEvent>()
+            .add_system(detect_hits);
+        let mut time = Time::default();
+        time.update_with_instant(Instant::now());
+        app.insert_resource(time);
+
+        let attacker = spawn_box(&mut app, -15.0);
+        app.world.entity_mut(attacker).insert(Hurtbox);
+        let victim = spawn_box(&mut app, 15.0);
+        app.world.entity_mut(victim).insert(Hurtbox);
+        // Out of reach, and touched but not hurtable.
+        let far = spawn_box(&mut app, 100.0);
+        app.world.entity_mut(far).insert(Hurtbox);
+        spawn_box(&mut app, 30.0);
+        // Both touch the victim, only the first one its attacker.
+        let first = spawn_hitbox(&mut app, 0.0, attacker);
+        let second = spawn_hitbox(&mut app, 25.0, attacker);
+
+        let mut reader = app.world.resource::<Events<HitEvent>>().get_reader();
+        let mut hits = Vec::new();
+        for _ in 0..5 {
+            let mut time = app.world.resource_mut::<Time>();
+            let now = time.last_update().unwrap() + Duration::from_secs_f32(1.0 / 60.0);
+            time.update_with_instant(now);
+            app.update();
+
+            let events = app.world.resource::<Events<HitEvent>>();
+            hits.extend(reader.iter(events).map(|it| {
+                assert_eq!(it.attacker, attacker);
+                assert_eq!(it.damage, 2.0);
+                (it.hitbox, it.victim)
+            }));
+        }
+        hits.sort();
+        let mut expected = vec![(first, victim), (second, victim)];
+        expected.sort();
+        assert_eq!(hits, expected);
+    }
+
+    #[test]
+    fn flipped_polygons_are_mirrored() {
+        let display = Display {
+            type_field: "boundingBox".to_string(),
+            sub_type: Some("polygon".to_string()),
+            vertices: vec![10.0, 0.0, 30.0, 0.0, 30.0, 20.0],
+            ..default()
+        };
+        let extent = |flip_x| {
+            let aabb = bounding_box_collider(&display, flip_x)
+                .unwrap()
+                .raw
+                .compute_local_aabb();
+            (aabb.mins.x, aabb.maxs.x)
+        };
+        assert_eq!(extent(false), (10.0, 30.0));
+        assert_eq!(extent(true), (-30.0, -10.0));
+    }
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, fmt::Debug};
 
 use bevy::{
-    asset::{AssetLoader, LoadContext, LoadedAsset},
+    asset::{AssetIoError, AssetLoader, LoadContext, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
     sprite::Rect,
@@ -20,7 +20,7 @@ impl AssetLoader for AnimationLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
-            let ske_root: SkeRoot = serde_json::from_slice(bytes)?;
+            let mut ske_root: SkeRoot = serde_json::from_slice(bytes)?;
             let obj_path = load_context
                 .path()
                 .to_str()
@@ -34,6 +34,24 @@ impl AssetLoader for AnimationLoader {
                     .as_slice(),
             )?;
 
+            // Clips drawn by hand until the exported file has them, e.g. from
+            // frames of other clips; the exported armatures win.
+            match load_context
+                .read_asset_bytes(format!("{}.placeholder_ske.json", obj_path))
+                .await
+            {
+                Ok(bytes) => {
+                    let placeholders: SkeRoot = serde_json::from_slice(&bytes)?;
+                    for armature in placeholders.armature {
+                        if !ske_root.armature.iter().any(|it| it.name == armature.name) {
+                            ske_root.armature.push(armature);
+                        }
+                    }
+                }
+                Err(AssetIoError::NotFound(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+
             let animation_data = AnimationData {
                 ske: ske_root,
                 tex: tex_root,
@@ -62,6 +80,20 @@ pub struct AnimationData {
     pub map: HashMap<String, u32>,
 }
 
+impl AnimationData {
+    /// Offset from the centre of the untrimmed frame to the centre of the packed
+    /// sub texture, in DragonBones (y-down) space.
+    pub fn texture_offset(&self, name: &str) -> Vec2 {
+        match self.tex.sub_texture.iter().find(|it| it.name == name) {
+            Some(it) if it.frame_width > 0 && it.frame_height > 0 => Vec2::new(
+                it.width / 2.0 - it.frame_x as f32 - it.frame_width as f32 / 2.0,
+                it.height / 2.0 - it.frame_y as f32 - it.frame_height as f32 / 2.0,
+            ),
+            _ => Vec2::ZERO,
+        }
+    }
+}
+
 pub struct Animation {}
 
 impl FromWorld for Animation {
@@ -135,110 +167,3 @@ impl FromWorld for Animation {
 //         println!("{:?}", p.extension());
 //     }
 // }
-
-// pub struct Condition<Args> {
-//     fun: Box<dyn FnMut(Args, PlayState) -> bool>,
-// }
-
-// pub trait ConditionFunc<Args> {
-//     fn get_func(self) -> Condition<Args>;
-// }
-
-// impl<F, Args> ConditionFunc<Args> for F
-// where
-//     F: FnMut(Args, PlayState) -> bool + 'static,
-// {
-//     fn get_func(self) -> Condition<Args> {
-//         Condition {
-//             fun: Box::new(self),
-//         }
-//     }
-// }
-
-// pub struct PlayState {
-//     done: bool,
-// }
-
-// #[derive()]
-// pub struct AnimationStatus<S, A>
-// where
-//     S: Eq + Hash,
-// {
-//     cur: S,
-//     map: HashMap<S, Vec<(S, Condition<A>)>>,
-// }
-
-// impl<S, A> AnimationStatus<S, A>
-// where
-//     S: Eq + Hash + Clone + Copy + Debug,
-//     A: Clone,
-// {
-//     pub fn new(start: S) -> Self {
-//         Self {
-//             cur: start,
-//             map: HashMap::new(),
-//         }
-//     }
-
-//     pub fn add_edge(&mut self, from: S, to: S, condition: impl ConditionFunc<A>) {
-//         self.map
-//             .entry(from)
-//             .or_insert(Vec::new())
-//             .push((to, condition.get_func()));
-//     }
-
-//     pub fn run(&mut self, arg: A) {
-//         for (to, cond) in self.map.get_mut(&self.cur).unwrap() {
-//             if cond.fun.call_mut((arg.clone(), PlayState { done: true })) {
-//                 self.cur = to.clone();
-//             }
-//         }
-//         println!("cur => {:?}", self.cur);
-//     }
-// }
-
-// pub fn on_done<T>(_: T, s: PlayState) -> bool {
-//     s.done
-// }
-
-// mod t {
-
-//     use super::{on_done, AnimationStatus};
-
-//     #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
-//     enum Status {
-//         Idle,
-//         Walk,
-//         Run,
-//         Jump,
-//         Attack,
-//     }
-
-//     #[derive(Clone)]
-//     struct Args {
-//         speed: f32,
-//     }
-
-//     fn t() {
-//         let mut a = AnimationStatus::new(Status::Idle);
-
-//         a.add_edge(Status::Idle, Status::Walk, |x: Args, _| x.speed > 0.1);
-//         a.add_edge(Status::Walk, Status::Idle, |x: Args, _| x.speed < 0.1);
-//         a.add_edge(Status::Walk, Status::Run, |x: Args, _| x.speed > 3.0);
-//         a.add_edge(Status::Run, Status::Jump, on_done);
-//         a.add_edge(Status::Jump, Status::Idle, on_done);
-
-//         a.run(Args { speed: 1.0 });
-//         a.run(Args { speed: 0.0 });
-//     }
-
-//     fn modulo(x: i32, n: i32) -> i32 {
-//         (x % n + n) % n
-//     }
-
-//     #[test]
-//     fn tt() {
-//         println!("{}", modulo(-2, 10));
-//         t();
-//     }
-// }
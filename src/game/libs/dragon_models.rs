@@ -14,6 +14,19 @@ pub struct SkeRoot {
     pub armature: Vec<Armature>,
 }
 
+impl SkeRoot {
+    /// Finds an animation by name together with the armature that owns it.
+    pub fn find_animation(&self, name: &str) -> Option<(&Armature, &Animation)> {
+        self.armature.iter().find_map(|armature| {
+            armature
+                .animation
+                .iter()
+                .find(|animation| animation.name == name)
+                .map(|animation| (armature, animation))
+        })
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Armature {
@@ -43,6 +56,20 @@ pub struct Aabb {
 #[serde(rename_all = "camelCase")]
 pub struct Bone {
     pub name: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(default)]
+    pub length: f32,
+    #[serde(default = "default_true")]
+    pub inherit_translation: bool,
+    #[serde(default = "default_true")]
+    pub inherit_rotation: bool,
+    #[serde(default = "default_true")]
+    pub inherit_scale: bool,
+    #[serde(default = "default_true")]
+    pub inherit_reflection: bool,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -51,7 +78,7 @@ pub struct ArmatureSlot {
     pub name: String,
     pub parent: String,
     #[serde(default)]
-    pub display_index: u32,
+    pub display_index: i32,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -71,40 +98,165 @@ pub struct SkinSlot {
 #[serde(rename_all = "camelCase")]
 pub struct Display {
     pub name: String,
+    #[serde(rename = "type", default)]
+    pub type_field: String,
+    pub path: Option<String>,
     pub transform: Option<Transform>,
+    /// `rectangle`, `ellipse` or `polygon` for `boundingBox` displays.
+    pub sub_type: Option<String>,
+    #[serde(default)]
+    pub width: f32,
+    #[serde(default)]
+    pub height: f32,
+    /// Polygon points as x, y pairs.
+    #[serde(default)]
+    pub vertices: Vec<f32>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Transform {
     #[serde(default)]
     pub x: f32,
     #[serde(default)]
     pub y: f32,
+    #[serde(default)]
+    pub sk_x: f32,
+    #[serde(default)]
+    pub sk_y: f32,
+    #[serde(default = "default_one")]
+    pub sc_x: f32,
+    #[serde(default = "default_one")]
+    pub sc_y: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            sk_x: 0.0,
+            sk_y: 0.0,
+            sc_x: 1.0,
+            sc_y: 1.0,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Animation {
     pub duration: f32,
-    #[serde(default)]
+    #[serde(default = "default_play_times")]
     pub play_times: i32,
     pub name: String,
+    #[serde(default)]
+    pub bone: Vec<AnimationBone>,
+    #[serde(default)]
     pub slot: Vec<AnimationSlot>,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimationBone {
+    pub name: String,
+    #[serde(default)]
+    pub translate_frame: Vec<TranslateFrame>,
+    #[serde(default)]
+    pub rotate_frame: Vec<RotateFrame>,
+    #[serde(default)]
+    pub scale_frame: Vec<ScaleFrame>,
+}
+
+/// Keyframe duration (in frames) and easing; a missing `tween_easing` means no tweening.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tween {
+    #[serde(default = "default_duration")]
+    pub duration: u32,
+    #[serde(default)]
+    pub tween_easing: Option<f32>,
+    #[serde(default)]
+    pub curve: Vec<f32>,
+}
+
+impl Default for Tween {
+    fn default() -> Self {
+        Self {
+            duration: 1,
+            tween_easing: None,
+            curve: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslateFrame {
+    #[serde(flatten)]
+    pub tween: Tween,
+    #[serde(default)]
+    pub x: f32,
+    #[serde(default)]
+    pub y: f32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotateFrame {
+    #[serde(flatten)]
+    pub tween: Tween,
+    #[serde(default)]
+    pub rotate: f32,
+    #[serde(default)]
+    pub clockwise: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScaleFrame {
+    #[serde(flatten)]
+    pub tween: Tween,
+    #[serde(default = "default_one")]
+    pub x: f32,
+    #[serde(default = "default_one")]
+    pub y: f32,
+}
+
+impl Default for ScaleFrame {
+    fn default() -> Self {
+        Self {
+            tween: Tween::default(),
+            x: 1.0,
+            y: 1.0,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AnimationSlot {
     pub name: String,
+    #[serde(default)]
     pub display_frame: Vec<DisplayFrame>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DisplayFrame {
+    #[serde(default = "default_duration")]
+    pub duration: u32,
     #[serde(default)]
-    pub value: u32,
+    pub value: i32,
+}
+
+impl Default for DisplayFrame {
+    fn default() -> Self {
+        Self {
+            duration: 1,
+            value: 0,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -135,13 +287,33 @@ pub struct TexRoot {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubTexture {
+    #[serde(default)]
     pub frame_x: i32,
+    #[serde(default)]
     pub frame_height: u32,
     pub y: f32,
+    #[serde(default)]
     pub frame_y: i32,
+    #[serde(default)]
     pub frame_width: u32,
     pub width: f32,
     pub height: f32,
     pub name: String,
     pub x: f32,
 }
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+fn default_duration() -> u32 {
+    1
+}
+
+fn default_play_times() -> i32 {
+    1
+}
@@ -0,0 +1,255 @@
+use bevy::prelude::*;
+
+use super::{
+    dragon_armature::{
+        pose_armatures, slot_display_index, slot_displays, spawn_armature_slots, DragonArmature,
+    },
+    dragon_loader::AnimationData,
+    dragon_models::{self, Armature, Display},
+};
+
+#[derive(Default)]
+pub struct DragonAnimationPlugin;
+
+impl Plugin for DragonAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DragonAnimationEvent>()
+            .add_system(advance_players.label(DragonAnimationSystem::Advance))
+            .add_system(
+                spawn_armature_slots
+                    .label(DragonAnimationSystem::Spawn)
+                    .after(DragonAnimationSystem::Advance),
+            )
+            .add_system(pose_armatures.after(DragonAnimationSystem::Spawn))
+            .add_system(update_sheet_sprites.after(DragonAnimationSystem::Advance));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DragonAnimationSystem {
+    Advance,
+    Spawn,
+}
+
+/// Sent by [`DragonAnimationPlayer`] each time a clip reaches its end.
+#[derive(Debug, Clone)]
+pub enum DragonAnimationEvent {
+    /// The clip wrapped around and keeps playing.
+    Looped { entity: Entity, animation: String },
+    /// The clip played `play_times` times and now holds its last frame.
+    Finished { entity: Entity, animation: String },
+}
+
+impl DragonAnimationEvent {
+    pub fn entity(&self) -> Entity {
+        match self {
+            DragonAnimationEvent::Looped { entity, .. } => *entity,
+            DragonAnimationEvent::Finished { entity, .. } => *entity,
+        }
+    }
+}
+
+/// Plays DragonBones clips of one [`AnimationData`].
+///
+/// Entities that also carry a [`DragonArmature`] are rendered as a skeleton,
+/// otherwise a `TextureAtlasSprite` on the same entity shows the sheet frame.
+#[derive(Component)]
+pub struct DragonAnimationPlayer {
+    pub data: Handle<AnimationData>,
+    pub speed: f32,
+    pub paused: bool,
+    animation: String,
+    /// Playhead, in frames of the owning armature.
+    frame: f32,
+    play_count: u32,
+    finished: bool,
+}
+
+impl Default for DragonAnimationPlayer {
+    fn default() -> Self {
+        Self {
+            data: Handle::default(),
+            speed: 1.0,
+            paused: false,
+            animation: String::new(),
+            frame: 0.0,
+            play_count: 0,
+            finished: false,
+        }
+    }
+}
+
+impl DragonAnimationPlayer {
+    pub fn new(data: Handle<AnimationData>, animation: impl Into<String>) -> Self {
+        Self {
+            data,
+            animation: animation.into(),
+            ..default()
+        }
+    }
+
+    /// Switches to `animation`, restarting it only if another clip was playing.
+    pub fn play(&mut self, animation: &str) -> &mut Self {
+        if self.animation != animation {
+            self.animation = animation.to_string();
+            self.restart();
+        }
+        self
+    }
+
+    pub fn restart(&mut self) -> &mut Self {
+        self.frame = 0.0;
+        self.play_count = 0;
+        self.finished = false;
+        self
+    }
+
+    pub fn animation(&self) -> &str {
+        &self.animation
+    }
+
+    pub fn frame(&self) -> f32 {
+        self.frame
+    }
+
+    /// Number of times the current clip has reached its end.
+    pub fn play_count(&self) -> u32 {
+        self.play_count
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn clip<'a>(
+        &self,
+        data: &'a AnimationData,
+    ) -> Option<(&'a Armature, &'a dragon_models::Animation)> {
+        data.ske.find_animation(&self.animation)
+    }
+
+    /// Current display of a `Sheet` armature, which has exactly one slot,
+    /// together with every display of that slot.
+    pub fn sheet_display<'a>(&self, data: &'a AnimationData) -> Option<(usize, &'a [Display])> {
+        let (armature, animation) = self.clip(data)?;
+        let slot = armature.slot.first()?;
+        let displays = slot_displays(armature, slot)?;
+        let index = usize::try_from(slot_display_index(animation, slot, self.frame)).ok()?;
+        displays.get(index)?;
+        Some((index, displays))
+    }
+
+    /// Moves the playhead `frames` forward, wrapping around as many times as
+    /// it passes the end. Returns how many times the clip looped and whether
+    /// it finished.
+    fn advance(&mut self, frames: f32, animation: &dragon_models::Animation) -> (u32, bool) {
+        let mut loops = 0;
+        self.frame += frames;
+        while self.frame >= animation.duration {
+            self.play_count += 1;
+            if animation.play_times > 0 && self.play_count >= animation.play_times as u32 {
+                self.frame = animation.duration;
+                self.finished = true;
+                return (loops, true);
+            }
+            self.frame -= animation.duration;
+            loops += 1;
+        }
+        (loops, false)
+    }
+}
+
+fn advance_players(
+    time: Res<Time>,
+    datas: Res<Assets<AnimationData>>,
+    mut events: EventWriter<DragonAnimationEvent>,
+    mut query: Query<(Entity, &mut DragonAnimationPlayer)>,
+) {
+    for (entity, mut player) in &mut query {
+        if player.paused || player.finished {
+            continue;
+        }
+        let (armature, animation) = match datas.get(&player.data).and_then(|data| player.clip(data))
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        if animation.duration <= 0.0 {
+            continue;
+        }
+
+        let frames = time.delta_seconds() * armature.frame_rate * player.speed;
+        let (loops, finished) = player.advance(frames, animation);
+        for _ in 0..loops {
+            events.send(DragonAnimationEvent::Looped {
+                entity,
+                animation: animation.name.clone(),
+            });
+        }
+        if finished {
+            events.send(DragonAnimationEvent::Finished {
+                entity,
+                animation: animation.name.clone(),
+            });
+        }
+    }
+}
+
+fn update_sheet_sprites(
+    datas: Res<Assets<AnimationData>>,
+    mut query: Query<(&DragonAnimationPlayer, &mut TextureAtlasSprite), Without<DragonArmature>>,
+) {
+    for (player, mut sprite) in &mut query {
+        let data = match datas.get(&player.data) {
+            Some(v) => v,
+            None => continue,
+        };
+        let index = player.sheet_display(data).and_then(|(index, displays)| {
+            let display = &displays[index];
+            data.map
+                .get(display.path.as_deref().unwrap_or(&display.name))
+        });
+        if let Some(index) = index {
+            if sprite.index != *index as usize {
+                sprite.index = *index as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(play_times: i32) -> dragon_models::Animation {
+        dragon_models::Animation {
+            duration: 10.0,
+            play_times,
+            name: "run".to_string(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn long_steps_count_every_loop() {
+        let animation = clip(0);
+        let mut player = DragonAnimationPlayer::new(Handle::default(), "run");
+        assert_eq!(player.advance(4.0, &animation), (0, false));
+        assert_eq!(player.advance(21.0, &animation), (2, false));
+        assert_eq!(player.play_count(), 2);
+        assert!((player.frame() - 5.0).abs() < 1e-4);
+        assert_eq!(player.advance(5.0, &animation), (1, false));
+        assert_eq!(player.frame(), 0.0);
+    }
+
+    #[test]
+    fn long_steps_stop_at_the_last_play() {
+        let animation = clip(3);
+        let mut player = DragonAnimationPlayer::new(Handle::default(), "run");
+        assert_eq!(player.advance(5.0, &animation), (0, false));
+        assert_eq!(player.advance(30.0, &animation), (2, true));
+        assert_eq!(player.play_count(), 3);
+        assert_eq!(player.frame(), 10.0);
+        assert!(player.is_finished());
+    }
+}
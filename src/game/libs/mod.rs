@@ -1,6 +1,45 @@
 mod dragon_models;
 mod dragon_loader;
+mod dragon_armature;
+mod dragon_player;
+mod dragon_hitbox;
+mod animation_graph;
+mod one_way_platform;
+mod character_controller;
 mod tiled_map;
+mod tiled_objects;
+mod tiled_collision;
+mod tiled_json;
+mod tiled_resources;
+mod tiled_animation;
+mod tiled_layers;
+mod tiled_projection;
+mod tiled_properties;
+mod navigation;
 
-pub use tiled_map::{TiledMapPlugin, TiledMap, TiledMapBundle};
+pub use tiled_map::{TiledMapPlugin, TiledMap, TiledMapBundle, TiledMapSpace, MapLoaded, MapUnloaded};
+pub use tiled_layers::{TiledLayerContext, TiledMapSettings, TiledParallax, TiledRepeatedImage};
+pub use tiled_projection::TiledProjection;
+pub use tiled_properties::{properties_to_json, TiledProperties};
+pub use tiled_animation::{TileAnimationClock, TiledTileAnimation};
+pub use tiled_objects::{TiledObject, TiledObjectAppExt, TiledObjectRegistry, TiledPoint};
 pub use dragon_loader::{AnimationLoader, AnimationData, Animation};
+pub use dragon_armature::{DragonArmature, DragonArmatureBundle, DragonSlot};
+pub use dragon_player::{DragonAnimationEvent, DragonAnimationPlayer, DragonAnimationPlugin};
+pub use dragon_hitbox::{
+    DragonHitboxPlugin, DragonHitboxes, HitEvent, Hitbox, HitboxFrames, HitboxSet, Hurtbox,
+};
+pub use character_controller::{
+    move_characters, CharacterController, CharacterControllerBundle, CharacterControllerPlugin,
+    CharacterInput, CharacterState, DashModel, JumpModel, WallModel,
+};
+pub use one_way_platform::{
+    DropThrough, OneWayPlatform, OneWayPlatformBundle, OneWayPlatformPlugin, OneWayPlatformQuery,
+};
+pub use navigation::{
+    NavAction, NavCell, NavGrid, NavStep, Navigation, NavigationDebug, NavigationPlugin,
+    NavigationSettings,
+};
+pub use animation_graph::{
+    run_state_machines, AnimationGraph, AnimationGraphPlugin, AnimationStateMachine,
+};
@@ -0,0 +1,801 @@
+//! Platformer navigation over the tile grid of loaded Tiled maps.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{
+    tiled_layers::flatten_layers,
+    tiled_map::{MapLoaded, MapUnloaded, TiledMap, TiledMapSpace},
+    tiled_objects::TiledObjectRegistry,
+    tiled_properties::TiledProperties,
+};
+
+/// Depth of the debug overlay, above the map layers.
+const DEBUG_Z: f32 = 50.0;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Navigation>()
+            .init_resource::<NavigationSettings>()
+            .init_resource::<NavigationDebug>()
+            .add_system(build_navigation)
+            .add_system(draw_navigation.after(build_navigation));
+    }
+}
+
+/// What agents can do, in tiles. Grids are built with the settings current
+/// when their map loads.
+pub struct NavigationSettings {
+    /// Free cells an agent needs, from its feet up.
+    pub agent_height: u32,
+    /// How high above its feet an agent's jump rises.
+    pub jump_height: u32,
+    /// How far sideways an agent can jump.
+    pub jump_distance: u32,
+    /// How far an agent may fall down a ledge or through a one-way platform.
+    pub max_fall: u32,
+}
+
+impl Default for NavigationSettings {
+    fn default() -> Self {
+        Self {
+            agent_height: 2,
+            jump_height: 3,
+            jump_distance: 4,
+            max_fall: 12,
+        }
+    }
+}
+
+/// Draws the navigation graph of every loaded map when enabled.
+#[derive(Default)]
+pub struct NavigationDebug {
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NavCell {
+    Empty,
+    Solid,
+    /// A one-way platform: stood on, but jumped and dropped through.
+    Platform,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NavAction {
+    Walk,
+    Jump,
+    Fall,
+}
+
+/// One step of a path: the action that reaches `cell`, whose centre is at
+/// `position` in the world.
+#[derive(Clone, Copy, Debug)]
+pub struct NavStep {
+    pub cell: UVec2,
+    pub position: Vec2,
+    pub action: NavAction,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct NavLink {
+    to: usize,
+    action: NavAction,
+    cost: f32,
+}
+
+/// Navigation grids of the loaded maps, keyed by map entity.
+#[derive(Default)]
+pub struct Navigation {
+    grids: HashMap<Entity, NavGrid>,
+}
+
+impl Navigation {
+    pub fn grid(&self, map: Entity) -> Option<&NavGrid> {
+        self.grids.get(&map)
+    }
+
+    /// Path between two world positions of the map entity `map`, see
+    /// [`NavGrid::find_path`].
+    pub fn find_path(&self, map: Entity, from: Vec2, to: Vec2) -> Option<Vec<NavStep>> {
+        self.grids.get(&map)?.find_path(from, to)
+    }
+}
+
+/// Cells of an orthogonal map, and the graph of the cells agents can stand in.
+///
+/// A cell is solid when a tile with collision or a collider object covers its
+/// centre; tiles and objects with a bool property `one_way` make platforms.
+/// Cells are addressed like in Tiled: column `x`, row `y` counted downwards.
+pub struct NavGrid {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: Vec2,
+    cells: Vec<NavCell>,
+    /// Space of the (top-level) map entity.
+    space: TiledMapSpace,
+    jump_height: u32,
+    nodes: HashMap<UVec2, usize>,
+    node_cells: Vec<UVec2>,
+    links: Vec<Vec<NavLink>>,
+}
+
+impl NavGrid {
+    fn new(
+        tiled_map: &TiledMap,
+        transform: Transform,
+        registry: &TiledObjectRegistry,
+        settings: &NavigationSettings,
+    ) -> Self {
+        let map = &tiled_map.map;
+        let mut grid = Self {
+            width: map.width,
+            height: map.height,
+            tile_size: Vec2::new(map.tile_width as f32, map.tile_height as f32),
+            cells: vec![NavCell::Empty; (map.width * map.height) as usize],
+            space: TiledMapSpace::new(map, transform),
+            jump_height: settings.jump_height,
+            nodes: HashMap::default(),
+            node_cells: Vec::new(),
+            links: Vec::new(),
+        };
+        grid.rasterize(map, registry);
+        grid.link(settings);
+        grid
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> NavCell {
+        if x < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return NavCell::Solid;
+        }
+        // Above the map is open sky.
+        if y < 0 {
+            return NavCell::Empty;
+        }
+        self.cells[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    /// Cell under a world position.
+    pub fn cell_at(&self, position: Vec2) -> Option<UVec2> {
+        let local = self
+            .space
+            .transform
+            .compute_matrix()
+            .inverse()
+            .transform_point3(position.extend(0.0))
+            .truncate();
+        let cell = (self.space.to_map(local) / self.tile_size).floor();
+        let inside = cell.x >= 0.0
+            && cell.y >= 0.0
+            && cell.x < self.width as f32
+            && cell.y < self.height as f32;
+        inside.then(|| cell.as_uvec2())
+    }
+
+    /// World position of a point in cell units, cell centres being whole numbers.
+    pub fn cell_to_world(&self, cell: Vec2) -> Vec2 {
+        let pixel = (cell + 0.5) * self.tile_size;
+        self.space
+            .to_world(self.space.to_local(pixel.x, pixel.y), 0.0)
+            .translation
+            .truncate()
+    }
+
+    /// Whether an agent can stand in `cell`.
+    pub fn is_node(&self, cell: UVec2) -> bool {
+        self.nodes.contains_key(&cell)
+    }
+
+    /// Every link of the graph, as (from, to, action).
+    pub fn links(&self) -> impl Iterator<Item = (UVec2, UVec2, NavAction)> + '_ {
+        self.links
+            .iter()
+            .enumerate()
+            .flat_map(move |(from, links)| {
+                links
+                    .iter()
+                    .map(move |link| (self.node_cells[from], self.node_cells[link.to], link.action))
+            })
+    }
+
+    /// Points of the arc jumped from `from` to `to`, in cell units.
+    pub fn jump_arc(&self, from: UVec2, to: UVec2) -> Vec<Vec2> {
+        let (start, end) = (from.as_vec2(), to.as_vec2());
+        // Quadratic curve peaking about `jump_height` above the higher end.
+        let apex = start.y.min(end.y) - self.jump_height as f32;
+        let control = Vec2::new(
+            (start.x + end.x) / 2.0,
+            2.0 * apex - (start.y + end.y) / 2.0,
+        );
+        // A few samples per cell travelled, so that no cell is skipped.
+        let span = (end - start).abs();
+        let samples = (span.x + span.y + self.jump_height as f32 * 2.0) as usize * 3 + 4;
+        (0..=samples)
+            .map(|i| {
+                let t = i as f32 / samples as f32;
+                start * (1.0 - t) * (1.0 - t) + control * 2.0 * t * (1.0 - t) + end * t * t
+            })
+            .collect()
+    }
+
+    /// Cheapest path from the node nearest below `from` to the one nearest
+    /// below `to`. The first step is the starting cell, reached by walking.
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<NavStep>> {
+        let start = self.node_below(self.cell_at(from)?)?;
+        let goal = self.node_below(self.cell_at(to)?)?;
+        let goal_position = self.node_cells[goal].as_vec2();
+        // Links cost at least half the distance they cover (falls are cheap),
+        // so half the distance never overestimates what is left.
+        let heuristic = |node: usize| self.node_cells[node].as_vec2().distance(goal_position) * 0.5;
+
+        let mut costs = vec![f32::INFINITY; self.node_cells.len()];
+        let mut came_from: Vec<Option<(usize, NavAction)>> = vec![None; self.node_cells.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0.0;
+        open.push(OpenNode {
+            node: start,
+            estimate: heuristic(start),
+        });
+
+        while let Some(OpenNode { node, .. }) = open.pop() {
+            if node == goal {
+                let mut steps = Vec::new();
+                let mut current = (goal, NavAction::Walk);
+                loop {
+                    let cell = self.node_cells[current.0];
+                    let previous = came_from[current.0];
+                    steps.push(NavStep {
+                        cell,
+                        position: self.cell_to_world(cell.as_vec2()),
+                        action: previous.map_or(NavAction::Walk, |(_, action)| action),
+                    });
+                    match previous {
+                        Some((node, action)) => current = (node, action),
+                        None => break,
+                    }
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+            for link in &self.links[node] {
+                let cost = costs[node] + link.cost;
+                if cost < costs[link.to] {
+                    costs[link.to] = cost;
+                    came_from[link.to] = Some((node, link.action));
+                    open.push(OpenNode {
+                        node: link.to,
+                        estimate: cost + heuristic(link.to),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The node in `cell`, or the first one below it.
+    fn node_below(&self, cell: UVec2) -> Option<usize> {
+        (cell.y..self.height).find_map(|y| self.nodes.get(&UVec2::new(cell.x, y)).copied())
+    }
+
+    fn set(&mut self, x: i32, y: i32, cell: NavCell) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let index = (y as u32 * self.width + x as u32) as usize;
+        // Solid wins over a platform drawn in the same cell.
+        if self.cells[index] != NavCell::Solid {
+            self.cells[index] = cell;
+        }
+    }
+
+    /// Marks the cells covered by tile collision and collider objects.
+    fn rasterize(&mut self, map: &tiled::Map, registry: &TiledObjectRegistry) {
+        for (layer, context) in flatten_layers(map) {
+            match layer.layer_type() {
+                tiled::LayerType::Tiles(tile_layer) => {
+                    let shift = (context.offset / self.tile_size).round().as_ivec2();
+                    for y in 0..self.height as i32 {
+                        for x in 0..self.width as i32 {
+                            let tile = match tile_layer.get_tile(x, y) {
+                                Some(v) => v,
+                                None => continue,
+                            };
+                            let tile = match tile.get_tile() {
+                                Some(v) => v,
+                                None => continue,
+                            };
+                            let collides = tile
+                                .collision
+                                .as_ref()
+                                .map_or(false, |collision| !collision.object_data().is_empty());
+                            if collides {
+                                self.set(x + shift.x, y + shift.y, kind(&tile.properties));
+                            }
+                        }
+                    }
+                }
+                tiled::LayerType::Objects(object_layer) => {
+                    for object in object_layer.object_data() {
+                        // Typed objects are gameplay (doors, spawn points...), not walls.
+                        if registry.get(&object.user_type).is_some() {
+                            continue;
+                        }
+                        let cell = kind(&object.properties);
+                        for y in 0..self.height as i32 {
+                            for x in 0..self.width as i32 {
+                                let center = (Vec2::new(x as f32, y as f32) + 0.5) * self.tile_size;
+                                if covers(object, center - context.offset) {
+                                    self.set(x, y, cell);
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether an agent with its feet in (x, y) fits there.
+    fn fits(&self, x: i32, y: i32, agent_height: u32) -> bool {
+        (0..agent_height as i32).all(|k| self.cell(x, y - k) != NavCell::Solid)
+    }
+
+    /// Finds the cells agents can stand in, and links them with walks, falls and jumps.
+    fn link(&mut self, settings: &NavigationSettings) {
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let stands = self.cell(x, y) == NavCell::Empty
+                    && self.cell(x, y + 1) != NavCell::Empty
+                    && self.fits(x, y, settings.agent_height);
+                if stands {
+                    let cell = UVec2::new(x as u32, y as u32);
+                    self.nodes.insert(cell, self.node_cells.len());
+                    self.node_cells.push(cell);
+                }
+            }
+        }
+
+        let node = |grid: &Self, x: i32, y: i32| {
+            if x < 0 || y < 0 {
+                return None;
+            }
+            grid.nodes.get(&UVec2::new(x as u32, y as u32)).copied()
+        };
+        let mut links = vec![Vec::new(); self.node_cells.len()];
+
+        for (index, cell) in self.node_cells.iter().enumerate() {
+            let (x, y) = (cell.x as i32, cell.y as i32);
+            let links = &mut links[index];
+
+            for dx in [-1, 1] {
+                if let Some(to) = node(self, x + dx, y) {
+                    links.push(NavLink {
+                        to,
+                        action: NavAction::Walk,
+                        cost: 1.0,
+                    });
+                } else if self.fits(x + dx, y, settings.agent_height) {
+                    // Off the ledge, down to the first place to stand.
+                    if let Some(to) = self.landing(x + dx, y + 1, settings.max_fall) {
+                        let fall = self.node_cells[to].y as i32 - y;
+                        links.push(NavLink {
+                            to,
+                            action: NavAction::Fall,
+                            cost: 1.0 + fall as f32 * 0.5,
+                        });
+                    }
+                }
+            }
+
+            // Through the one-way platform underfoot.
+            if self.cell(x, y + 1) == NavCell::Platform {
+                if let Some(to) = self.landing(x, y + 2, settings.max_fall) {
+                    let fall = self.node_cells[to].y as i32 - y;
+                    links.push(NavLink {
+                        to,
+                        action: NavAction::Fall,
+                        cost: 1.0 + fall as f32 * 0.5,
+                    });
+                }
+            }
+
+            let reach = settings.jump_distance as i32;
+            let rise = settings.jump_height as i32;
+            for ty in y - rise..=y + rise {
+                for tx in x - reach..=x + reach {
+                    let to = match node(self, tx, ty) {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    // Straight up only makes sense to reach a platform above.
+                    if to == index || (tx == x && ty >= y) {
+                        continue;
+                    }
+                    if ty == y && self.walkable(x, tx, y) {
+                        continue;
+                    }
+                    let target = UVec2::new(tx as u32, ty as u32);
+                    if !self.arc_clear(*cell, target, settings.agent_height) {
+                        continue;
+                    }
+                    links.push(NavLink {
+                        to,
+                        action: NavAction::Jump,
+                        cost: 2.0 + cell.as_vec2().distance(target.as_vec2()),
+                    });
+                }
+            }
+        }
+        self.links = links;
+    }
+
+    /// First node at or below (x, y) within `max_fall` cells, if nothing solid is in the way.
+    fn landing(&self, x: i32, y: i32, max_fall: u32) -> Option<usize> {
+        for y in y..=y + max_fall as i32 {
+            if self.cell(x, y) == NavCell::Solid {
+                return None;
+            }
+            if y >= 0 {
+                if let Some(node) = self.nodes.get(&UVec2::new(x as u32, y as u32)) {
+                    return Some(*node);
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether every cell between columns `from` and `to` of row `y` can be walked.
+    fn walkable(&self, from: i32, to: i32, y: i32) -> bool {
+        (from.min(to)..=from.max(to))
+            .all(|x| x >= 0 && self.nodes.contains_key(&UVec2::new(x as u32, y as u32)))
+    }
+
+    fn arc_clear(&self, from: UVec2, to: UVec2, agent_height: u32) -> bool {
+        self.jump_arc(from, to).into_iter().all(|point| {
+            let cell = point.round().as_ivec2();
+            self.fits(cell.x, cell.y, agent_height)
+        })
+    }
+}
+
+/// Solid, or a platform when flagged with a bool property `one_way`.
+fn kind(properties: &tiled::Properties) -> NavCell {
+    let properties = TiledProperties::non_empty(properties);
+    match properties.and_then(|v| v.bool("one_way")) {
+        Some(true) => NavCell::Platform,
+        _ => NavCell::Solid,
+    }
+}
+
+/// Whether an object's shape covers a point of its layer (pixels, y down).
+fn covers(object: &tiled::ObjectData, point: Vec2) -> bool {
+    // Into the object's frame: Tiled rotates clockwise around its origin.
+    let local =
+        Mat2::from_angle(-object.rotation.to_radians()) * (point - Vec2::new(object.x, object.y));
+    match &object.shape {
+        tiled::ObjectShape::Rect { width, height } => {
+            local.x >= 0.0 && local.y >= 0.0 && local.x <= *width && local.y <= *height
+        }
+        tiled::ObjectShape::Ellipse { width, height } => {
+            let radius = Vec2::new(*width, *height) / 2.0;
+            ((local - radius) / radius).length_squared() <= 1.0
+        }
+        tiled::ObjectShape::Polygon { points } => {
+            // Even-odd rule.
+            let mut inside = false;
+            for (i, &(x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(i + 1) % points.len()];
+                if (y1 > local.y) != (y2 > local.y)
+                    && local.x < (x2 - x1) * (local.y - y1) / (y2 - y1) + x1
+                {
+                    inside = !inside;
+                }
+            }
+            inside
+        }
+        _ => false,
+    }
+}
+
+/// A node to visit, ordered so that the binary heap pops the lowest estimate first.
+struct OpenNode {
+    node: usize,
+    estimate: f32,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+fn build_navigation(
+    mut loaded_events: EventReader<MapLoaded>,
+    mut unloaded_events: EventReader<MapUnloaded>,
+    mut navigation: ResMut<Navigation>,
+    maps: Res<Assets<TiledMap>>,
+    registry: Res<TiledObjectRegistry>,
+    settings: Res<NavigationSettings>,
+    transforms: Query<&Transform>,
+    map_entities: Query<(), With<Handle<TiledMap>>>,
+) {
+    for event in unloaded_events.iter() {
+        navigation.grids.remove(&event.entity);
+    }
+    // Map entities despawned without unloading first. Checked before
+    // retaining so that the debug overlay isn't redrawn every frame.
+    if navigation
+        .grids
+        .keys()
+        .any(|entity| !map_entities.contains(*entity))
+    {
+        navigation
+            .grids
+            .retain(|entity, _| map_entities.contains(*entity));
+    }
+    for event in loaded_events.iter() {
+        let tiled_map = match maps.get(&event.map) {
+            Some(v) => v,
+            None => continue,
+        };
+        if tiled_map.map.orientation != tiled::Orientation::Orthogonal || tiled_map.map.infinite() {
+            info!("no navigation for non-orthogonal or infinite maps");
+            continue;
+        }
+        let transform = transforms.get(event.entity).copied().unwrap_or_default();
+        let grid = NavGrid::new(tiled_map, transform, &registry, &settings);
+        navigation.grids.insert(event.entity, grid);
+    }
+}
+
+#[derive(Component)]
+struct NavigationOverlay;
+
+fn draw_navigation(
+    mut commands: Commands,
+    navigation: Res<Navigation>,
+    debug: Res<NavigationDebug>,
+    overlay: Query<Entity, With<NavigationOverlay>>,
+) {
+    if !navigation.is_changed() && !debug.is_changed() {
+        return;
+    }
+    for entity in &overlay {
+        commands.entity(entity).despawn();
+    }
+    if !debug.enabled {
+        return;
+    }
+
+    for grid in navigation.grids.values() {
+        for cell in &grid.node_cells {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::WHITE,
+                        custom_size: Some(Vec2::splat(4.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(
+                        grid.cell_to_world(cell.as_vec2()).extend(DEBUG_Z),
+                    ),
+                    ..default()
+                })
+                .insert(NavigationOverlay);
+        }
+        for (from, to, action) in grid.links() {
+            let (points, color) = match action {
+                NavAction::Walk => (vec![from.as_vec2(), to.as_vec2()], Color::GREEN),
+                NavAction::Fall => (
+                    vec![
+                        from.as_vec2(),
+                        Vec2::new(to.x as f32, from.y as f32),
+                        to.as_vec2(),
+                    ],
+                    Color::CYAN,
+                ),
+                NavAction::Jump => (grid.jump_arc(from, to), Color::rgba(1.0, 1.0, 0.0, 0.3)),
+            };
+            for pair in points.windows(2) {
+                let (a, b) = (grid.cell_to_world(pair[0]), grid.cell_to_world(pair[1]));
+                let delta = b - a;
+                commands
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(Vec2::new(delta.length(), 1.5)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(((a + b) / 2.0).extend(DEBUG_Z))
+                            .with_rotation(Quat::from_rotation_z(delta.y.atan2(delta.x))),
+                        ..default()
+                    })
+                    .insert(NavigationOverlay);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tiled_projection::TiledProjection, *};
+
+    fn settings() -> NavigationSettings {
+        NavigationSettings {
+            agent_height: 2,
+            jump_height: 3,
+            jump_distance: 5,
+            max_fall: 12,
+        }
+    }
+
+    /// A grid drawn row by row: `#` is solid, `=` a platform, `.` empty.
+    fn grid(rows: &[&str]) -> NavGrid {
+        let settings = settings();
+        let width = rows[0].len() as u32;
+        let height = rows.len() as u32;
+        let tile_size = Vec2::splat(16.0);
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => NavCell::Solid,
+                '=' => NavCell::Platform,
+                _ => NavCell::Empty,
+            })
+            .collect();
+        let mut grid = NavGrid {
+            width,
+            height,
+            tile_size,
+            cells,
+            space: TiledMapSpace {
+                size: Vec2::new(width as f32, height as f32) * tile_size,
+                transform: Transform::identity(),
+                projection: TiledProjection {
+                    orientation: tiled::Orientation::Orthogonal,
+                    size: UVec2::new(width, height),
+                    tile_size,
+                    stagger_x: false,
+                    stagger_odd: false,
+                    side_length: 0.0,
+                },
+            },
+            jump_height: settings.jump_height,
+            nodes: HashMap::default(),
+            node_cells: Vec::new(),
+            links: Vec::new(),
+        };
+        grid.link(&settings);
+        grid
+    }
+
+    fn links_from(grid: &NavGrid, x: u32, y: u32) -> Vec<(UVec2, NavAction)> {
+        grid.links()
+            .filter(|(from, _, _)| *from == UVec2::new(x, y))
+            .map(|(_, to, action)| (to, action))
+            .collect()
+    }
+
+    #[test]
+    fn walks_and_falls_off_ledges() {
+        let grid = grid(&[
+            "........", //
+            "........", "........", "###.....", "########",
+        ]);
+        assert!(grid.is_node(UVec2::new(2, 2)));
+        assert!(grid.is_node(UVec2::new(3, 3)));
+        assert!(!grid.is_node(UVec2::new(3, 2)));
+
+        let links = links_from(&grid, 0, 2);
+        assert!(links.contains(&(UVec2::new(1, 2), NavAction::Walk)));
+        let links = links_from(&grid, 2, 2);
+        assert!(links.contains(&(UVec2::new(3, 3), NavAction::Fall)));
+        // Nothing to fall off on the way back, the ledge is jumped onto.
+        let links = links_from(&grid, 3, 3);
+        assert!(links.contains(&(UVec2::new(2, 2), NavAction::Jump)));
+        assert!(!links.iter().any(|(_, action)| *action == NavAction::Fall));
+    }
+
+    #[test]
+    fn landing_stops_at_solid_cells_and_max_fall() {
+        let grid = grid(&[
+            "........", //
+            "........", "........", "###.....", "########",
+        ]);
+        let node = grid.nodes[&UVec2::new(3, 3)];
+        assert_eq!(grid.landing(3, 0, 12), Some(node));
+        assert_eq!(grid.landing(3, 0, 3), Some(node));
+        assert_eq!(grid.landing(3, 0, 2), None);
+        // Solid ground under the starting point.
+        assert_eq!(grid.landing(1, 3, 12), None);
+    }
+
+    #[test]
+    fn drops_and_jumps_through_platforms() {
+        let grid = grid(&[
+            "......", //
+            "......", "..==..", "......", "......", "######",
+        ]);
+        assert!(grid.is_node(UVec2::new(2, 1)));
+        assert!(grid.is_node(UVec2::new(2, 4)));
+
+        let links = links_from(&grid, 2, 1);
+        assert!(links.contains(&(UVec2::new(2, 4), NavAction::Fall)));
+        let links = links_from(&grid, 2, 4);
+        assert!(links.contains(&(UVec2::new(2, 1), NavAction::Jump)));
+        // Standing under the platform isn't dropping through it.
+        assert!(!links.iter().any(|(_, action)| *action == NavAction::Fall));
+    }
+
+    #[test]
+    fn jump_arcs_are_blocked_by_ceilings() {
+        let open = grid(&[
+            "........", //
+            "........", "........", "##....##", "##....##", "########",
+        ]);
+        assert!(open.arc_clear(UVec2::new(1, 2), UVec2::new(6, 2), 2));
+        assert!(links_from(&open, 1, 2).contains(&(UVec2::new(6, 2), NavAction::Jump)));
+
+        let covered = grid(&[
+            "........", //
+            "..####..", "........", "##....##", "##....##", "########",
+        ]);
+        assert!(!covered.arc_clear(UVec2::new(1, 2), UVec2::new(6, 2), 2));
+        assert!(!links_from(&covered, 1, 2).contains(&(UVec2::new(6, 2), NavAction::Jump)));
+    }
+
+    #[test]
+    fn finds_the_cheapest_path() {
+        let grid = grid(&[
+            "........", //
+            "........", "........", "##....##", "##....##", "########",
+        ]);
+        let from = grid.cell_to_world(Vec2::new(0.0, 2.0));
+        let to = grid.cell_to_world(Vec2::new(7.0, 2.0));
+        let steps = grid.find_path(from, to).unwrap();
+        let steps: Vec<(UVec2, NavAction)> =
+            steps.iter().map(|step| (step.cell, step.action)).collect();
+        // Over the pit rather than through it.
+        assert_eq!(
+            steps,
+            vec![
+                (UVec2::new(0, 2), NavAction::Walk),
+                (UVec2::new(1, 2), NavAction::Walk),
+                (UVec2::new(6, 2), NavAction::Jump),
+                (UVec2::new(7, 2), NavAction::Walk),
+            ]
+        );
+
+        // Into the pit, from above it.
+        let above = grid.cell_to_world(Vec2::new(3.0, 0.0));
+        let steps = grid.find_path(from, above).unwrap();
+        assert_eq!(steps.last().unwrap().cell, UVec2::new(3, 4));
+
+        let mut navigation = Navigation::default();
+        let map = Entity::from_raw(1);
+        navigation.grids.insert(map, grid);
+        assert!(navigation.find_path(map, from, to).is_some());
+        assert!(navigation
+            .find_path(Entity::from_raw(2), from, to)
+            .is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::{
+    ActiveHooks, ContactModificationContextView, PhysicsHooksWithQuery,
+    PhysicsHooksWithQueryResource, Vect, Velocity,
+};
+
+/// How far from straight up a contact normal may lean and still hold a body
+/// on a one-way platform, in radians.
+const ALLOWED_ANGLE: f32 = 0.8;
+
+/// How long a character ignores one-way platforms after dropping through, in seconds.
+const DROP_THROUGH_TIME: f32 = 0.25;
+
+/// Upward speed above which a body passes through one-way platforms, so that
+/// the solver's jitter doesn't drop bodies resting on them.
+const RISING_SPEED: f32 = 1.0;
+
+/// What the one-way platform hook reads: the physics plugin must be added as
+/// `RapierPhysicsPlugin::<OneWayPlatformQuery>`.
+pub type OneWayPlatformQuery<'a> = (
+    Option<&'a OneWayPlatform>,
+    Option<&'a DropThrough>,
+    Option<&'a Velocity>,
+);
+
+pub struct OneWayPlatformPlugin;
+
+impl Plugin for OneWayPlatformPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PhysicsHooksWithQueryResource::<OneWayPlatformQuery>(
+            Box::new(OneWayPlatformHook),
+        ))
+        .add_system(tick_drop_through);
+    }
+}
+
+/// A collider that bodies can pass through from below and stand on from
+/// above, along its local up axis.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct OneWayPlatform;
+
+#[derive(Bundle)]
+pub struct OneWayPlatformBundle {
+    pub platform: OneWayPlatform,
+    /// Rapier only runs the hook for colliders that ask for it.
+    pub hooks: ActiveHooks,
+}
+
+impl Default for OneWayPlatformBundle {
+    fn default() -> Self {
+        Self {
+            platform: OneWayPlatform,
+            hooks: ActiveHooks::MODIFY_SOLVER_CONTACTS,
+        }
+    }
+}
+
+/// Lets a character fall through the one-way platform it stands on, be it a
+/// dynamic body or one moved by the character controller.
+#[derive(Component, Default, Debug, Clone)]
+pub struct DropThrough {
+    remaining: f32,
+}
+
+impl DropThrough {
+    pub fn start(&mut self) {
+        self.remaining = DROP_THROUGH_TIME;
+    }
+
+    pub fn is_dropping(&self) -> bool {
+        self.remaining > 0.0
+    }
+}
+
+fn tick_drop_through(time: Res<Time>, mut query: Query<&mut DropThrough>) {
+    for mut drop in &mut query {
+        if drop.is_dropping() {
+            drop.remaining -= time.delta_seconds();
+        }
+    }
+}
+
+/// Keeps the contacts between a one-way platform and another body only when
+/// the body lands on top of it: not while it moves up through the platform,
+/// comes from the side or below, or drops through it.
+///
+/// Only dynamic bodies go through the solver, so only they are affected.
+/// Kinematic characters are moved by `move_characters`, whose casts skip the
+/// platforms themselves while rising or dropping.
+struct OneWayPlatformHook;
+
+impl<'a> PhysicsHooksWithQuery<OneWayPlatformQuery<'a>> for OneWayPlatformHook {
+    fn modify_solver_contacts(
+        &self,
+        mut context: ContactModificationContextView,
+        query: &Query<OneWayPlatformQuery<'a>>,
+    ) {
+        let is_platform = |entity| matches!(query.get(entity), Ok((Some(_), _, _)));
+
+        // Contact normals point from the first collider to the second, and
+        // are expressed in the first collider's space.
+        let (allowed_normal, other) = if is_platform(context.collider1()) {
+            (Vect::Y, context.collider2())
+        } else if is_platform(context.collider2()) {
+            (-Vect::Y, context.collider1())
+        } else {
+            return;
+        };
+
+        if let Ok((_, drop, velocity)) = query.get(other) {
+            let passing = match (drop, velocity) {
+                (Some(drop), _) if drop.is_dropping() => true,
+                (_, Some(velocity)) => velocity.linvel.y > RISING_SPEED,
+                _ => false,
+            };
+            if passing {
+                context.raw.solver_contacts.clear();
+                return;
+            }
+        }
+        context.update_as_oneway_platform(&allowed_normal, ALLOWED_ANGLE);
+    }
+}
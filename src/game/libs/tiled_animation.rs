@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::TileTexture;
+
+/// Drives every animated tile. Pausing it freezes them all on their current frame.
+pub struct TileAnimationClock {
+    pub speed: f32,
+    pub paused: bool,
+    /// Animation time in seconds, scaled by `speed`.
+    elapsed: f32,
+}
+
+impl Default for TileAnimationClock {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            paused: false,
+            elapsed: 0.0,
+        }
+    }
+}
+
+/// Frames of a tile animated in its tileset, as (tile id, seconds).
+#[derive(Component, Debug, Clone)]
+pub struct TiledTileAnimation {
+    frames: Vec<(u32, f32)>,
+    duration: f32,
+}
+
+impl TiledTileAnimation {
+    pub fn new(frames: &[tiled::Frame]) -> Option<Self> {
+        if frames.is_empty() {
+            return None;
+        }
+        let frames: Vec<(u32, f32)> = frames
+            .iter()
+            .map(|frame| (frame.tile_id, frame.duration as f32 / 1000.0))
+            .collect();
+        let duration = frames.iter().map(|(_, duration)| duration).sum();
+        Some(Self { frames, duration })
+    }
+
+    /// Tile id shown `time` seconds into the animation, which loops.
+    pub fn tile_at(&self, time: f32) -> u32 {
+        if self.duration <= 0.0 {
+            return self.frames[0].0;
+        }
+        let mut time = time.rem_euclid(self.duration);
+        for (tile, duration) in self.frames.iter() {
+            if time < *duration {
+                return *tile;
+            }
+            time -= duration;
+        }
+        self.frames[self.frames.len() - 1].0
+    }
+}
+
+pub(super) fn animate_tiles(
+    time: Res<Time>,
+    mut clock: ResMut<TileAnimationClock>,
+    mut tiles: Query<(&TiledTileAnimation, &mut TileTexture)>,
+    mut sprites: Query<(&TiledTileAnimation, &mut TextureAtlasSprite)>,
+) {
+    if clock.paused {
+        return;
+    }
+    clock.elapsed += time.delta_seconds() * clock.speed;
+
+    for (animation, mut texture) in &mut tiles {
+        let tile = animation.tile_at(clock.elapsed);
+        if texture.0 != tile {
+            texture.0 = tile;
+        }
+    }
+    // Tiles of non-orthogonal maps are sprites.
+    for (animation, mut sprite) in &mut sprites {
+        let tile = animation.tile_at(clock.elapsed) as usize;
+        if sprite.index != tile {
+            sprite.index = tile;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn animation(frames: &[(u32, f32)]) -> TiledTileAnimation {
+        TiledTileAnimation {
+            frames: frames.to_vec(),
+            duration: frames.iter().map(|(_, duration)| duration).sum(),
+        }
+    }
+
+    #[test]
+    fn tile_at_wraps_around() {
+        let animation = animation(&[(1, 0.5), (3, 0.5)]);
+        assert_eq!(animation.tile_at(0.0), 1);
+        assert_eq!(animation.tile_at(0.7), 3);
+        assert_eq!(animation.tile_at(1.2), 1);
+        assert_eq!(animation.tile_at(2.7), 3);
+        assert_eq!(animation.tile_at(-0.2), 3);
+    }
+
+    #[test]
+    fn tile_at_skips_zero_duration_frames() {
+        let animation = animation(&[(1, 0.5), (2, 0.0), (3, 0.5)]);
+        assert_eq!(animation.tile_at(0.4), 1);
+        assert_eq!(animation.tile_at(0.5), 3);
+        assert_eq!(animation.tile_at(1.0), 1);
+
+        let still = animation(&[(4, 0.0), (5, 0.0)]);
+        assert_eq!(still.tile_at(0.0), 4);
+        assert_eq!(still.tile_at(3.0), 4);
+    }
+
+    #[test]
+    fn paused_clock_holds_the_frame() {
+        let mut app = App::new();
+        app.init_resource::<TileAnimationClock>()
+            .add_system(animate_tiles);
+        let mut time = Time::default();
+        time.update_with_instant(Instant::now());
+        app.insert_resource(time);
+        let tile = app
+            .world
+            .spawn()
+            .insert(animation(&[(1, 0.1), (2, 0.1)]))
+            .insert(TextureAtlasSprite::default())
+            .id();
+
+        let step = |app: &mut App, seconds: f32| {
+            let mut time = app.world.resource_mut::<Time>();
+            let now = time.last_update().unwrap() + Duration::from_secs_f32(seconds);
+            time.update_with_instant(now);
+            app.update();
+            app.world.get::<TextureAtlasSprite>(tile).unwrap().index
+        };
+        assert_eq!(step(&mut app, 0.05), 1);
+        assert_eq!(step(&mut app, 0.1), 2);
+
+        app.world.resource_mut::<TileAnimationClock>().paused = true;
+        assert_eq!(step(&mut app, 0.1), 2);
+        assert_eq!(step(&mut app, 1.0), 2);
+
+        app.world.resource_mut::<TileAnimationClock>().paused = false;
+        assert_eq!(step(&mut app, 0.1), 1);
+    }
+}
@@ -0,0 +1,343 @@
+use std::f32::consts::TAU;
+
+use bevy::{prelude::*, transform::TransformBundle};
+use bevy_rapier2d::prelude::{Collider, Vect};
+
+use super::{
+    one_way_platform::OneWayPlatformBundle,
+    tiled_map::{TileRegion, TiledMapSpace},
+    tiled_projection::TiledProjection,
+    tiled_properties::TiledProperties,
+};
+
+/// Points used to approximate ellipses drawn in the tile collision editor,
+/// or projected onto isometric maps.
+const ELLIPSE_SEGMENTS: usize = 16;
+
+/// Collision shapes drawn on tileset tiles, collected for a rectangle of one tile layer.
+///
+/// On orthogonal maps, tiles whose collision is a single rectangle covering
+/// the whole tile are merged with their solid neighbours into larger
+/// rectangles. Every other shape, and every shape on the other orientations,
+/// gets a collider of its own, placed with the tile image in its cell. Tiles
+/// with a bool property `one_way` become one-way platforms, merged only with
+/// each other.
+pub(super) struct TileColliders {
+    projection: TiledProjection,
+    region: TileRegion,
+    width: u32,
+    height: u32,
+    tile_size: Vec2,
+    solid: Vec<bool>,
+    one_way: Vec<bool>,
+    shapes: Vec<TileShape>,
+}
+
+/// Outline of a collision object drawn on a tile.
+struct TileShape {
+    /// Points in layer pixels (y down).
+    points: Vec<Vec2>,
+    closed: bool,
+    one_way: bool,
+}
+
+impl TileColliders {
+    /// Collects the collision of the tiles of `region`.
+    pub fn new(projection: &TiledProjection, region: TileRegion) -> Self {
+        let (width, height) = (region.width, region.height);
+        Self {
+            projection: *projection,
+            region,
+            width,
+            height,
+            tile_size: projection.tile_size,
+            solid: vec![false; (width * height) as usize],
+            one_way: vec![false; (width * height) as usize],
+            shapes: Vec::new(),
+        }
+    }
+
+    /// Adds the collision of the tile placed at column `x`, row `y` of the rectangle
+    /// (Tiled coordinates, counted from its top-left corner).
+    pub fn add_tile(&mut self, x: u32, y: u32, map_tile: &tiled::LayerTile) {
+        let tile = match map_tile.get_tile() {
+            Some(v) => v,
+            None => return,
+        };
+        let collision = match &tile.collision {
+            Some(v) => v,
+            None => return,
+        };
+        let one_way = TiledProperties::non_empty(&tile.properties).and_then(|v| v.bool("one_way"))
+            == Some(true);
+        let tileset = map_tile.get_tileset();
+        let size = Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32);
+
+        let objects = collision.object_data();
+        let orthogonal = self.projection.orientation == tiled::Orientation::Orthogonal;
+        if let [object] = objects {
+            if orthogonal && size == self.tile_size && covers(object, size) {
+                let cells = if one_way {
+                    &mut self.one_way
+                } else {
+                    &mut self.solid
+                };
+                cells[(y * self.width + x) as usize] = true;
+                return;
+            }
+        }
+
+        // Tile images are drawn from the bottom-left corner of their cell.
+        let cell = self
+            .projection
+            .cell_origin(self.region.x + x as i32, self.region.y + y as i32)
+            - self.projection.cell_origin(self.region.x, self.region.y);
+        let origin = Vec2::new(cell.x, cell.y + self.tile_size.y - size.y);
+        for object in objects {
+            let (points, closed) = match outline(object) {
+                Some(v) => v,
+                None => continue,
+            };
+            let points = points
+                .into_iter()
+                .map(|point| origin + flip(point, size, map_tile))
+                .collect();
+            self.shapes.push(TileShape {
+                points,
+                closed,
+                one_way,
+            });
+        }
+    }
+
+    /// Spawns the colliders with the top-left corner of the region's first cell
+    /// at map point `offset`.
+    pub fn spawn(
+        self,
+        commands: &mut Commands,
+        space: &TiledMapSpace,
+        offset: Vec2,
+    ) -> Vec<Entity> {
+        let mut spawned = Vec::new();
+        for (cells, one_way) in [(&self.solid, false), (&self.one_way, true)] {
+            for (x, y, w, h) in merge_cells(cells, self.width, self.height) {
+                let min = offset + Vec2::new(x as f32, y as f32) * self.tile_size;
+                let half = Vec2::new(w as f32, h as f32) * self.tile_size / 2.0;
+                let center = space.to_local(min.x + half.x, min.y + half.y);
+                let mut entity = commands.spawn();
+                entity
+                    .insert(Collider::cuboid(half.x, half.y))
+                    .insert_bundle(TransformBundle::from(space.to_world(center, 0.0)));
+                if one_way {
+                    entity.insert_bundle(OneWayPlatformBundle::default());
+                }
+                spawned.push(entity.id());
+            }
+        }
+
+        for shape in self.shapes {
+            if shape.points.len() < 2 {
+                continue;
+            }
+            let points: Vec<Vec2> = shape
+                .points
+                .iter()
+                .map(|point| space.to_local(offset.x + point.x, offset.y + point.y))
+                .collect();
+            let min = points.iter().copied().reduce(Vec2::min).unwrap();
+            let max = points.iter().copied().reduce(Vec2::max).unwrap();
+            let center = (min + max) / 2.0;
+            let vertices: Vec<Vect> = points.iter().map(|point| *point - center).collect();
+
+            let collider = if shape.closed && vertices.len() >= 3 {
+                let count = vertices.len() as u32;
+                let indices: Vec<[u32; 2]> = (0..count).map(|i| [i, (i + 1) % count]).collect();
+                Collider::convex_decomposition(&vertices, &indices)
+            } else {
+                Collider::polyline(vertices, None)
+            };
+            let mut entity = commands.spawn();
+            entity
+                .insert(collider)
+                .insert_bundle(TransformBundle::from(space.to_world(center, 0.0)));
+            if shape.one_way {
+                entity.insert_bundle(OneWayPlatformBundle::default());
+            }
+            spawned.push(entity.id());
+        }
+        spawned
+    }
+}
+
+/// Whether a collision object is a rectangle filling the whole tile.
+fn covers(object: &tiled::ObjectData, size: Vec2) -> bool {
+    match object.shape {
+        tiled::ObjectShape::Rect { width, height } => {
+            object.rotation == 0.0
+                && object.x.abs() < 0.5
+                && object.y.abs() < 0.5
+                && (width - size.x).abs() < 0.5
+                && (height - size.y).abs() < 0.5
+        }
+        _ => false,
+    }
+}
+
+/// Outline of a collision object in tile pixels (y down) and whether it is closed.
+fn outline(object: &tiled::ObjectData) -> Option<(Vec<Vec2>, bool)> {
+    let (points, closed) = match &object.shape {
+        tiled::ObjectShape::Rect { width, height } => (
+            vec![
+                Vec2::ZERO,
+                Vec2::new(*width, 0.0),
+                Vec2::new(*width, *height),
+                Vec2::new(0.0, *height),
+            ],
+            true,
+        ),
+        tiled::ObjectShape::Ellipse { width, height } => {
+            (ellipse_points(Vec2::new(*width, *height)), true)
+        }
+        tiled::ObjectShape::Polygon { points } => (
+            points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect(),
+            true,
+        ),
+        tiled::ObjectShape::Polyline { points } => (
+            points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect(),
+            false,
+        ),
+        _ => return None,
+    };
+
+    // Tiled rotates clockwise around the object's origin, which is a
+    // positive angle once y points down.
+    let rotation = Mat2::from_angle(object.rotation.to_radians());
+    let position = Vec2::new(object.x, object.y);
+    Some((
+        points
+            .into_iter()
+            .map(|point| position + rotation * point)
+            .collect(),
+        closed,
+    ))
+}
+
+/// Outline of an ellipse filling a box of `size` whose top-left corner is the origin.
+pub(super) fn ellipse_points(size: Vec2) -> Vec<Vec2> {
+    let radius = size / 2.0;
+    (0..ELLIPSE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * TAU;
+            radius + radius * Vec2::new(angle.cos(), angle.sin())
+        })
+        .collect()
+}
+
+/// Applies the tile's flags to a point of its collision, diagonal flip first like Tiled does.
+fn flip(mut point: Vec2, mut size: Vec2, map_tile: &tiled::LayerTile) -> Vec2 {
+    if map_tile.flip_d {
+        point = Vec2::new(point.y, point.x);
+        size = Vec2::new(size.y, size.x);
+    }
+    if map_tile.flip_h {
+        point.x = size.x - point.x;
+    }
+    if map_tile.flip_v {
+        point.y = size.y - point.y;
+    }
+    point
+}
+
+/// Greedily covers the solid cells with rectangles `(x, y, width, height)`,
+/// growing each one along its row first and then downwards.
+fn merge_cells(solid: &[bool], width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let mut used = vec![false; solid.len()];
+    let mut rects = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            if !solid[index(x, y)] || used[index(x, y)] {
+                continue;
+            }
+
+            let mut w = 1;
+            while x + w < width && solid[index(x + w, y)] && !used[index(x + w, y)] {
+                w += 1;
+            }
+
+            let mut h = 1;
+            while y + h < height
+                && (x..x + w).all(|cx| solid[index(cx, y + h)] && !used[index(cx, y + h)])
+            {
+                h += 1;
+            }
+
+            for cy in y..y + h {
+                for cx in x..x + w {
+                    used[index(cx, cy)] = true;
+                }
+            }
+            rects.push((x, y, w, h));
+        }
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses rows of `#` (solid) and `.` (empty).
+    fn grid(rows: &[&str]) -> (Vec<bool>, u32, u32) {
+        let solid = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect();
+        (solid, rows[0].len() as u32, rows.len() as u32)
+    }
+
+    fn covered(rects: &[(u32, u32, u32, u32)], width: u32, height: u32) -> Vec<u32> {
+        let mut count = vec![0; (width * height) as usize];
+        for &(x, y, w, h) in rects {
+            for cy in y..y + h {
+                for cx in x..x + w {
+                    count[(cy * width + cx) as usize] += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn merges_a_block_into_one_rect() {
+        let (solid, width, height) = grid(&["....", ".##.", ".##.", "...."]);
+        assert_eq!(merge_cells(&solid, width, height), vec![(1, 1, 2, 2)]);
+    }
+
+    #[test]
+    fn grows_rows_before_columns() {
+        let (solid, width, height) = grid(&["###", "#..", "#.."]);
+        assert_eq!(
+            merge_cells(&solid, width, height),
+            vec![(0, 0, 3, 1), (0, 1, 1, 2)]
+        );
+    }
+
+    #[test]
+    fn covers_every_solid_cell_once() {
+        let rows = ["#.##.#", "####..", ".##.##", "#....#"];
+        let (solid, width, height) = grid(&rows);
+        let rects = merge_cells(&solid, width, height);
+        let count = covered(&rects, width, height);
+        for (i, is_solid) in solid.iter().enumerate() {
+            assert_eq!(count[i], u32::from(*is_solid), "cell {}", i);
+        }
+    }
+
+    #[test]
+    fn empty_grid_has_no_rects() {
+        let (solid, width, height) = grid(&["...", "..."]);
+        assert!(merge_cells(&solid, width, height).is_empty());
+    }
+}
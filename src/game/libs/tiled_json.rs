@@ -0,0 +1,503 @@
+//! Tiled's JSON map format, rewritten into TMX so that both formats go
+//! through the same parser and produce the same `tiled::Map`.
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, Context};
+use serde_json::{Map, Value};
+
+type Object = Map<String, Value>;
+
+const MAP_ATTRIBUTES: &[&str] = &[
+    "version",
+    "tiledversion",
+    "class",
+    "orientation",
+    "renderorder",
+    "width",
+    "height",
+    "tilewidth",
+    "tileheight",
+    "hexsidelength",
+    "staggeraxis",
+    "staggerindex",
+    "parallaxoriginx",
+    "parallaxoriginy",
+    "backgroundcolor",
+    "infinite",
+    "nextlayerid",
+    "nextobjectid",
+];
+
+const TILESET_ATTRIBUTES: &[&str] = &[
+    "firstgid",
+    "source",
+    "name",
+    "class",
+    "tilewidth",
+    "tileheight",
+    "spacing",
+    "margin",
+    "tilecount",
+    "columns",
+    "objectalignment",
+];
+
+const LAYER_ATTRIBUTES: &[&str] = &[
+    "id",
+    "name",
+    "class",
+    "width",
+    "height",
+    "opacity",
+    "visible",
+    "tintcolor",
+    "offsetx",
+    "offsety",
+    "parallaxx",
+    "parallaxy",
+    "color",
+    "draworder",
+    "repeatx",
+    "repeaty",
+];
+
+const OBJECT_ATTRIBUTES: &[&str] = &[
+    "id", "name", "type", "class", "x", "y", "width", "height", "rotation", "gid", "visible",
+    "template",
+];
+
+const TEXT_ATTRIBUTES: &[&str] = &[
+    "fontfamily",
+    "pixelsize",
+    "wrap",
+    "color",
+    "bold",
+    "italic",
+    "underline",
+    "strikeout",
+    "kerning",
+    "halign",
+    "valign",
+];
+
+/// Converts the bytes of a `.tmj` or `.json` map into an equivalent TMX document.
+pub(super) fn json_to_tmx(bytes: &[u8]) -> anyhow::Result<String> {
+    let root: Value = serde_json::from_slice(bytes).context("invalid Tiled JSON map")?;
+    let map = root
+        .as_object()
+        .ok_or_else(|| anyhow!("a Tiled JSON map must be an object"))?;
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    open(&mut out, "map", map, MAP_ATTRIBUTES);
+    write_properties(&mut out, map);
+    for tileset in objects(map, "tilesets") {
+        write_tileset(&mut out, tileset);
+    }
+    write_layers(&mut out, map)?;
+    out.push_str("</map>\n");
+    Ok(out)
+}
+
+fn write_tileset(out: &mut String, tileset: &Object) {
+    open(out, "tileset", tileset, TILESET_ATTRIBUTES);
+    if let Some(offset) = tileset.get("tileoffset").and_then(Value::as_object) {
+        empty(out, "tileoffset", offset, &["x", "y"]);
+    }
+    write_properties(out, tileset);
+    write_image(out, tileset);
+    for tile in objects(tileset, "tiles") {
+        open(out, "tile", tile, &["id", "type", "class", "probability"]);
+        write_properties(out, tile);
+        write_image(out, tile);
+        if let Some(group) = tile.get("objectgroup").and_then(Value::as_object) {
+            write_object_group(out, group);
+        }
+        if let Some(frames) = tile.get("animation").and_then(Value::as_array) {
+            out.push_str("<animation>");
+            for frame in frames.iter().filter_map(Value::as_object) {
+                empty(out, "frame", frame, &["tileid", "duration"]);
+            }
+            out.push_str("</animation>");
+        }
+        out.push_str("</tile>\n");
+    }
+    out.push_str("</tileset>\n");
+}
+
+/// JSON keeps the image of tilesets, tiles and image layers as plain fields.
+fn write_image(out: &mut String, object: &Object) {
+    let source = match object.get("image").and_then(Value::as_str) {
+        Some(v) if !v.is_empty() => v,
+        _ => return,
+    };
+    let _ = write!(out, "<image source=\"{}\"", escape(source));
+    for (json, tmx) in [
+        ("imagewidth", "width"),
+        ("imageheight", "height"),
+        ("transparentcolor", "trans"),
+    ] {
+        if let Some(value) = object.get(json).and_then(scalar) {
+            let _ = write!(out, " {}=\"{}\"", tmx, escape(&value));
+        }
+    }
+    out.push_str("/>\n");
+}
+
+fn write_layers(out: &mut String, parent: &Object) -> anyhow::Result<()> {
+    for layer in objects(parent, "layers") {
+        match layer.get("type").and_then(Value::as_str) {
+            Some("tilelayer") => write_tile_layer(out, layer)?,
+            Some("objectgroup") => write_object_group(out, layer),
+            Some("imagelayer") => {
+                open(out, "imagelayer", layer, LAYER_ATTRIBUTES);
+                write_properties(out, layer);
+                write_image(out, layer);
+                out.push_str("</imagelayer>\n");
+            }
+            Some("group") => {
+                open(out, "group", layer, LAYER_ATTRIBUTES);
+                write_properties(out, layer);
+                write_layers(out, layer)?;
+                out.push_str("</group>\n");
+            }
+            other => return Err(anyhow!("unknown Tiled layer type {:?}", other)),
+        }
+    }
+    Ok(())
+}
+
+fn write_tile_layer(out: &mut String, layer: &Object) -> anyhow::Result<()> {
+    open(out, "layer", layer, LAYER_ATTRIBUTES);
+    write_properties(out, layer);
+
+    let encoding = layer
+        .get("encoding")
+        .and_then(Value::as_str)
+        .unwrap_or("csv");
+    let _ = write!(out, "<data encoding=\"{}\"", encoding);
+    if let Some(compression) = layer.get("compression").and_then(Value::as_str) {
+        if !compression.is_empty() {
+            let _ = write!(out, " compression=\"{}\"", compression);
+        }
+    }
+    out.push('>');
+    if let Some(data) = layer.get("data") {
+        write_data(out, data)?;
+    }
+    // Infinite maps store their tiles in chunks instead.
+    for chunk in objects(layer, "chunks") {
+        open(out, "chunk", chunk, &["x", "y", "width", "height"]);
+        if let Some(data) = chunk.get("data") {
+            write_data(out, data)?;
+        }
+        out.push_str("</chunk>\n");
+    }
+    out.push_str("</data>\n</layer>\n");
+    Ok(())
+}
+
+/// Tile data is either an array of gids or an already encoded base64 string.
+fn write_data(out: &mut String, data: &Value) -> anyhow::Result<()> {
+    match data {
+        Value::Array(gids) => {
+            let gids: Vec<String> = gids.iter().map(|gid| gid.to_string()).collect();
+            out.push_str(&gids.join(","));
+        }
+        Value::String(encoded) => out.push_str(encoded),
+        _ => return Err(anyhow!("unsupported Tiled layer data {}", data)),
+    }
+    Ok(())
+}
+
+fn write_object_group(out: &mut String, group: &Object) {
+    open(out, "objectgroup", group, LAYER_ATTRIBUTES);
+    write_properties(out, group);
+    for object in objects(group, "objects") {
+        open(out, "object", object, OBJECT_ATTRIBUTES);
+        write_properties(out, object);
+        if object.get("ellipse").and_then(Value::as_bool) == Some(true) {
+            out.push_str("<ellipse/>");
+        }
+        if object.get("point").and_then(Value::as_bool) == Some(true) {
+            out.push_str("<point/>");
+        }
+        for shape in ["polygon", "polyline"] {
+            if let Some(points) = object.get(shape).and_then(Value::as_array) {
+                let points: Vec<String> = points
+                    .iter()
+                    .filter_map(Value::as_object)
+                    .map(|point| {
+                        format!(
+                            "{},{}",
+                            point.get("x").and_then(scalar).unwrap_or_default(),
+                            point.get("y").and_then(scalar).unwrap_or_default()
+                        )
+                    })
+                    .collect();
+                let _ = write!(out, "<{} points=\"{}\"/>", shape, points.join(" "));
+            }
+        }
+        if let Some(text) = object.get("text").and_then(Value::as_object) {
+            open(out, "text", text, TEXT_ATTRIBUTES);
+            let content = text.get("text").and_then(Value::as_str).unwrap_or_default();
+            let _ = write!(out, "{}</text>", escape(content));
+        }
+        out.push_str("</object>\n");
+    }
+    out.push_str("</objectgroup>\n");
+}
+
+fn write_properties(out: &mut String, object: &Object) {
+    let properties: Vec<&Object> = objects(object, "properties").collect();
+    if properties.is_empty() {
+        return;
+    }
+    out.push_str("<properties>\n");
+    for property in properties {
+        let name = property
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let kind = property
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or("string");
+        let class = property.get("propertytype").and_then(Value::as_str);
+        write_property(
+            out,
+            name,
+            kind,
+            class,
+            property.get("value").unwrap_or(&Value::Null),
+        );
+    }
+    out.push_str("</properties>\n");
+}
+
+fn write_property(out: &mut String, name: &str, kind: &str, class: Option<&str>, value: &Value) {
+    let _ = write!(out, "<property name=\"{}\" type=\"{}\"", escape(name), kind);
+    if let Some(class) = class.filter(|it| !it.is_empty()) {
+        let _ = write!(out, " propertytype=\"{}\"", escape(class));
+    }
+
+    // Class values hold their members, typed by the project file rather
+    // than the map, so their types are guessed from the JSON values.
+    if kind == "class" {
+        out.push_str(">\n<properties>\n");
+        for (name, value) in value.as_object().into_iter().flatten() {
+            let kind = match value {
+                Value::Bool(_) => "bool",
+                Value::Number(v) if v.is_f64() => "float",
+                Value::Number(_) => "int",
+                Value::Object(_) => "class",
+                _ => "string",
+            };
+            write_property(out, name, kind, None, value);
+        }
+        out.push_str("</properties>\n</property>\n");
+        return;
+    }
+
+    let value = match value {
+        Value::String(v) => v.clone(),
+        Value::Bool(v) => v.to_string(),
+        Value::Number(v) => v.to_string(),
+        _ => String::new(),
+    };
+    // Attribute values lose their line breaks, multi-line strings go in the body.
+    if value.contains('\n') {
+        let _ = writeln!(out, ">{}</property>", escape(&value));
+    } else {
+        let _ = writeln!(out, " value=\"{}\"/>", escape(&value));
+    }
+}
+
+fn objects<'a>(parent: &'a Object, key: &str) -> impl Iterator<Item = &'a Object> {
+    parent
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object)
+}
+
+fn open(out: &mut String, tag: &str, object: &Object, attributes: &[&str]) {
+    let _ = write!(out, "<{}", tag);
+    write_attributes(out, object, attributes);
+    out.push_str(">\n");
+}
+
+fn empty(out: &mut String, tag: &str, object: &Object, attributes: &[&str]) {
+    let _ = write!(out, "<{}", tag);
+    write_attributes(out, object, attributes);
+    out.push_str("/>\n");
+}
+
+fn write_attributes(out: &mut String, object: &Object, attributes: &[&str]) {
+    for name in attributes {
+        if let Some(value) = object.get(*name).and_then(scalar) {
+            let _ = write!(out, " {}=\"{}\"", name, escape(&value));
+        }
+    }
+}
+
+/// TMX spells booleans as 0/1.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(v) => Some(v.clone()),
+        Value::Number(v) => Some(v.to_string()),
+        Value::Bool(v) => Some(if *v { "1" } else { "0" }.to_string()),
+        _ => None,
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::Path};
+
+    use super::*;
+
+    /// Serves the converted map and nothing else.
+    struct MapReader(String);
+
+    impl tiled::ResourceReader for MapReader {
+        type Resource = io::Cursor<Vec<u8>>;
+        type Error = io::Error;
+
+        fn read_from(&mut self, _path: &Path) -> Result<Self::Resource, Self::Error> {
+            Ok(io::Cursor::new(self.0.clone().into_bytes()))
+        }
+    }
+
+    fn parse(json: &str) -> tiled::Map {
+        let tmx = json_to_tmx(json.as_bytes()).unwrap();
+        tiled::Loader::with_cache_and_reader(tiled::DefaultResourceCache::new(), MapReader(tmx))
+            .load_tmx_map("map.tmx")
+            .unwrap()
+    }
+
+    const MAP: &str = r#"{
+        "type": "map", "version": "1.9", "orientation": "orthogonal",
+        "renderorder": "right-down", "infinite": false,
+        "width": 3, "height": 2, "tilewidth": 32, "tileheight": 32,
+        "properties": [
+            {"name": "title", "type": "string", "value": "first\nlevel"},
+            {"name": "dark", "type": "bool", "value": true}
+        ],
+        "tilesets": [],
+        "layers": [
+            {
+                "type": "tilelayer", "id": 1, "name": "ground", "width": 3, "height": 2,
+                "opacity": 1, "visible": true, "x": 0, "y": 0,
+                "data": [0, 0, 0, 0, 0, 0]
+            },
+            {
+                "type": "group", "id": 2, "name": "things", "opacity": 1, "visible": false,
+                "layers": [{
+                    "type": "objectgroup", "id": 3, "name": "objects", "opacity": 1,
+                    "visible": true, "draworder": "topdown",
+                    "objects": [{
+                        "id": 1, "name": "door", "x": 8, "y": 16, "width": 32, "height": 64,
+                        "rotation": 0, "visible": true,
+                        "properties": [
+                            {"name": "to", "type": "string", "value": "02"},
+                            {
+                                "name": "spawn", "type": "class", "propertytype": "Spawn",
+                                "value": {"x": 3, "y": 1.5, "left": true, "name": "start"}
+                            }
+                        ]
+                    }]
+                }]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn converts_map_and_layers() {
+        let map = parse(MAP);
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!((map.tile_width, map.tile_height), (32, 32));
+        assert_eq!(
+            map.properties.get("title"),
+            Some(&tiled::PropertyValue::StringValue(
+                "first\nlevel".to_string()
+            ))
+        );
+        assert_eq!(
+            map.properties.get("dark"),
+            Some(&tiled::PropertyValue::BoolValue(true))
+        );
+
+        let layers: Vec<_> = map.layers().collect();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].name, "ground");
+        assert!(matches!(layers[0].layer_type(), tiled::LayerType::Tiles(_)));
+        assert!(!layers[1].visible);
+    }
+
+    #[test]
+    fn converts_objects_and_class_properties() {
+        let map = parse(MAP);
+        let group = match map.layers().nth(1).unwrap().layer_type() {
+            tiled::LayerType::Group(group) => group,
+            _ => panic!("expected a group layer"),
+        };
+        let objects = match group.layers().next().unwrap().layer_type() {
+            tiled::LayerType::Objects(objects) => objects,
+            _ => panic!("expected an object layer"),
+        };
+        let door = objects.objects().next().unwrap();
+        assert_eq!(door.name, "door");
+        assert_eq!((door.x, door.y), (8.0, 16.0));
+        assert!(matches!(
+            door.shape,
+            tiled::ObjectShape::Rect { width, height } if width == 32.0 && height == 64.0
+        ));
+        assert_eq!(
+            door.properties.get("to"),
+            Some(&tiled::PropertyValue::StringValue("02".to_string()))
+        );
+
+        let (property_type, spawn) = match door.properties.get("spawn") {
+            Some(tiled::PropertyValue::ClassValue {
+                property_type,
+                properties,
+            }) => (property_type, properties),
+            other => panic!("expected a class value, got {:?}", other),
+        };
+        assert_eq!(property_type, "Spawn");
+        assert_eq!(spawn.get("x"), Some(&tiled::PropertyValue::IntValue(3)));
+        assert_eq!(spawn.get("y"), Some(&tiled::PropertyValue::FloatValue(1.5)));
+        assert_eq!(
+            spawn.get("left"),
+            Some(&tiled::PropertyValue::BoolValue(true))
+        );
+        assert_eq!(
+            spawn.get("name"),
+            Some(&tiled::PropertyValue::StringValue("start".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_layers() {
+        let json = r#"{"width": 1, "height": 1, "layers": [{"type": "sparkles"}]}"#;
+        assert!(json_to_tmx(json.as_bytes()).is_err());
+        assert!(json_to_tmx(b"[]").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Layer layout: group layers, image layers and parallax.
+//!
+//! Besides Tiled's own settings, layers read two custom properties:
+//! - `z` (float): depth of the layer, see [`TiledMapSettings::layer_z`].
+//! - `scale` (float, image layers only): Tiled can't scale image layers, so
+//!   this enlarges the image, letting low resolution backdrops such as the
+//!   one of `01.tmx` cover the map without being resized by hand.
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use super::tiled_map::{TiledMap, TiledMapSpace};
+
+/// How map layers are laid out and which of them are spawned.
+pub struct TiledMapSettings {
+    /// Depth between consecutive layers, in Tiled's drawing order. A layer
+    /// float property `z` places that layer at an explicit depth instead.
+    pub layer_z_step: f32,
+    /// Spawn hidden tile and image layers invisible instead of skipping them.
+    /// Their tile collision and objects are spawned either way.
+    pub spawn_hidden_layers: bool,
+    /// Distance from the active camera, in map pixels, within which the chunks
+    /// of infinite maps are spawned. Chunks further away are despawned.
+    pub chunk_radius: f32,
+}
+
+impl Default for TiledMapSettings {
+    fn default() -> Self {
+        Self {
+            layer_z_step: 1.0,
+            spawn_hidden_layers: false,
+            chunk_radius: 1024.0,
+        }
+    }
+}
+
+impl TiledMapSettings {
+    /// Depth of the `index`-th layer in drawing order, relative to the map entity.
+    pub fn layer_z(&self, index: usize, layer: &tiled::Layer) -> f32 {
+        match layer.properties.get("z") {
+            Some(tiled::PropertyValue::FloatValue(v)) => *v,
+            Some(tiled::PropertyValue::IntValue(v)) => *v as f32,
+            _ => index as f32 * self.layer_z_step,
+        }
+    }
+}
+
+/// A layer's settings combined with those of the groups it is nested in.
+#[derive(Clone, Copy, Debug)]
+pub struct TiledLayerContext {
+    /// Offset in map pixels (y down).
+    pub offset: Vec2,
+    pub opacity: f32,
+    pub tint: Color,
+    pub visible: bool,
+    pub parallax: Vec2,
+}
+
+impl Default for TiledLayerContext {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            opacity: 1.0,
+            tint: Color::WHITE,
+            visible: true,
+            parallax: Vec2::ONE,
+        }
+    }
+}
+
+impl TiledLayerContext {
+    /// Context of `layer` nested in this one; offsets add up, the rest multiplies.
+    pub fn child(&self, layer: &tiled::Layer) -> Self {
+        let tint = match layer.tint_color {
+            Some(tint) => Color::rgba_u8(tint.red, tint.green, tint.blue, tint.alpha),
+            None => Color::WHITE,
+        };
+        Self {
+            offset: self.offset + Vec2::new(layer.offset_x, layer.offset_y),
+            opacity: self.opacity * layer.opacity,
+            tint: Color::rgba(
+                self.tint.r() * tint.r(),
+                self.tint.g() * tint.g(),
+                self.tint.b() * tint.b(),
+                self.tint.a() * tint.a(),
+            ),
+            visible: self.visible && layer.visible,
+            parallax: self.parallax * Vec2::new(layer.parallax_x, layer.parallax_y),
+        }
+    }
+
+    /// Tint with the opacity folded into the alpha.
+    pub fn color(&self) -> Color {
+        let mut color = self.tint;
+        color.set_a(color.a() * self.opacity);
+        color
+    }
+}
+
+/// Every non-group layer of the map in drawing order, with its context.
+pub fn flatten_layers(map: &tiled::Map) -> Vec<(tiled::Layer, TiledLayerContext)> {
+    let mut layers = Vec::new();
+    push_layers(map.layers(), TiledLayerContext::default(), &mut layers);
+    layers
+}
+
+fn push_layers<'map>(
+    children: impl Iterator<Item = tiled::Layer<'map>>,
+    parent: TiledLayerContext,
+    layers: &mut Vec<(tiled::Layer<'map>, TiledLayerContext)>,
+) {
+    for layer in children {
+        let context = parent.child(&layer);
+        match layer.layer_type() {
+            tiled::LayerType::Group(group) => push_layers(group.layers(), context, layers),
+            _ => layers.push((layer, context)),
+        }
+    }
+}
+
+/// Moves a layer by Tiled's parallax factor as the active camera scrolls.
+///
+/// Like in Tiled, a factor of 1 moves with the map, 0 stays fixed on screen,
+/// and the offset is measured from the map's top-left corner.
+#[derive(Component, Debug, Clone)]
+pub struct TiledParallax {
+    pub factor: Vec2,
+    /// Translation relative to the map entity before parallax.
+    pub base: Vec3,
+    /// Top-left corner of the map, relative to the map entity.
+    pub origin: Vec2,
+    /// Size of one copy of a repeated image, zero on axes that don't repeat.
+    pub repeat: Vec2,
+}
+
+impl TiledParallax {
+    pub fn new(context: &TiledLayerContext, base: Vec3, space: &TiledMapSpace) -> Self {
+        Self {
+            factor: context.parallax,
+            base,
+            origin: space.to_local(0.0, 0.0),
+            repeat: Vec2::ZERO,
+        }
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.factor == Vec2::ONE && self.repeat == Vec2::ZERO
+    }
+}
+
+/// Spawns an image layer as sprites under one entity placed at the layer offset.
+pub(super) fn spawn_image_layer(
+    commands: &mut Commands,
+    tiled_map: &TiledMap,
+    space: &TiledMapSpace,
+    layer: &tiled::Layer,
+    image_layer: &tiled::ImageLayer,
+    context: &TiledLayerContext,
+    z: f32,
+) -> Option<Entity> {
+    let image = image_layer.image.as_ref()?;
+    let texture = tiled_map.images.get(&image.source)?;
+
+    let scale = match layer.properties.get("scale") {
+        Some(tiled::PropertyValue::FloatValue(v)) => *v,
+        Some(tiled::PropertyValue::IntValue(v)) => *v as f32,
+        _ => 1.0,
+    };
+    let size = Vec2::new(image.width as f32, image.height as f32) * scale;
+    let color = context.color();
+
+    let base = space.to_local(context.offset.x, context.offset.y).extend(z);
+    let mut parallax = TiledParallax::new(context, base, space);
+    parallax.repeat = Vec2::new(
+        if image_layer.repeat_x { size.x } else { 0.0 },
+        if image_layer.repeat_y { size.y } else { 0.0 },
+    );
+
+    let mut repeated = TiledRepeatedImage {
+        texture: texture.clone(),
+        size,
+        color,
+        copies: IVec2::ZERO,
+    };
+
+    let mut entity = commands.spawn_bundle(SpatialBundle {
+        transform: Transform::from_translation(base),
+        visibility: Visibility {
+            is_visible: context.visible,
+        },
+        ..default()
+    });
+    // Repeated images get their copies from `cover_repeated_images`.
+    if parallax.repeat == Vec2::ZERO {
+        entity.with_children(|parent| repeated.spawn_copies(parent));
+    } else {
+        repeated.copies = IVec2::splat(-1);
+        entity.insert(repeated);
+    }
+    if !parallax.is_static() {
+        entity.insert(parallax);
+    }
+    Some(entity.id())
+}
+
+/// The image of a repeated image layer, copied around the layer entity,
+/// which the parallax system keeps next to the camera.
+#[derive(Component, Debug, Clone)]
+pub struct TiledRepeatedImage {
+    texture: Handle<Image>,
+    size: Vec2,
+    color: Color,
+    /// Copies on each side of the first one, along each axis; negative
+    /// until they are first laid out.
+    copies: IVec2,
+}
+
+impl TiledRepeatedImage {
+    fn spawn_copies(&self, parent: &mut ChildBuilder) {
+        for x in -self.copies.x..=self.copies.x {
+            for y in -self.copies.y..=self.copies.y {
+                parent.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: self.color,
+                        custom_size: Some(self.size),
+                        anchor: Anchor::TopLeft,
+                        ..default()
+                    },
+                    texture: self.texture.clone(),
+                    transform: Transform::from_xyz(
+                        x as f32 * self.size.x,
+                        -y as f32 * self.size.y,
+                        0.0,
+                    ),
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+/// Respawns the copies of repeated images when the active camera's view
+/// needs more or fewer of them to be covered, e.g. after a window resize.
+pub(super) fn cover_repeated_images(
+    mut commands: Commands,
+    cameras: Query<(&Camera, &OrthographicProjection)>,
+    mut layers: Query<(
+        Entity,
+        &TiledParallax,
+        &mut TiledRepeatedImage,
+        &GlobalTransform,
+    )>,
+) {
+    let view = match cameras.iter().find(|(camera, _)| camera.is_active) {
+        Some((_, projection)) => {
+            Vec2::new(
+                projection.right - projection.left,
+                projection.top - projection.bottom,
+            ) * projection.scale
+        }
+        None => return,
+    };
+
+    for (entity, parallax, mut image, transform) in &mut layers {
+        let (scale, _, _) = transform.to_scale_rotation_translation();
+        let half_view = view / 2.0 / scale.truncate().abs();
+        // The layer is snapped within half a copy of the view centre, hence
+        // the extra copy on each side.
+        let copies = |repeat: f32, half_view: f32| {
+            if repeat > 0.0 {
+                (half_view / repeat).ceil() as i32 + 1
+            } else {
+                0
+            }
+        };
+        let copies = IVec2::new(
+            copies(parallax.repeat.x, half_view.x),
+            copies(parallax.repeat.y, half_view.y),
+        );
+        if copies == image.copies {
+            continue;
+        }
+        image.copies = copies;
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| image.spawn_copies(parent));
+    }
+}
+
+pub(super) fn apply_parallax(
+    cameras: Query<(&Camera, &Transform), Without<TiledParallax>>,
+    maps: Query<&GlobalTransform, With<Handle<TiledMap>>>,
+    mut layers: Query<(&Parent, &TiledParallax, &mut Transform)>,
+) {
+    let camera = match cameras.iter().find(|(camera, _)| camera.is_active) {
+        Some((_, transform)) => transform.translation,
+        None => return,
+    };
+
+    for (parent, parallax, mut transform) in &mut layers {
+        let map = match maps.get(parent.get()) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let view = map.affine().inverse().transform_point3(camera).truncate();
+
+        let mut translation =
+            parallax.base.truncate() + (view - parallax.origin) * (Vec2::ONE - parallax.factor);
+        // Snap repeated images by whole copies so they keep covering the view.
+        if parallax.repeat.x > 0.0 {
+            translation.x +=
+                ((view.x - translation.x) / parallax.repeat.x).round() * parallax.repeat.x;
+        }
+        if parallax.repeat.y > 0.0 {
+            translation.y +=
+                ((view.y - translation.y) / parallax.repeat.y).round() * parallax.repeat.y;
+        }
+        transform.translation = translation.extend(parallax.base.z);
+    }
+}
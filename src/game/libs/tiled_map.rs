@@ -1,24 +1,62 @@
-use std::io::BufReader;
+use std::path::PathBuf;
 
 use bevy::{
     asset::{AssetLoader, AssetPath, LoadedAsset},
+    ecs::system::EntityCommands,
     prelude::*,
     reflect::TypeUuid,
-    transform::TransformBundle,
+    transform::{TransformBundle, TransformSystem},
     utils::HashMap,
 };
 use bevy_ecs_tilemap::prelude::*;
-use bevy_rapier2d::prelude::{Collider, Vect};
+
+use super::{
+    one_way_platform::OneWayPlatformBundle,
+    tiled_animation::{animate_tiles, TileAnimationClock, TiledTileAnimation},
+    tiled_collision::TileColliders,
+    tiled_json::json_to_tmx,
+    tiled_layers::{
+        apply_parallax, cover_repeated_images, flatten_layers, spawn_image_layer,
+        TiledLayerContext, TiledMapSettings, TiledParallax,
+    },
+    tiled_objects::{TiledObject, TiledObjectRegistry, TiledPoint},
+    tiled_projection::{spawn_tile_sprites, tileset_atlas, TiledProjection},
+    tiled_properties::TiledProperties,
+    tiled_resources::{
+        load_tmx_map, normalize_path, prune_resource_cache, reload_dependent_maps, TiledDependency,
+        TiledDependencyLoader, TiledResourceCache,
+    },
+};
 
 #[derive(Default)]
 pub struct TiledMapPlugin;
 
 impl Plugin for TiledMapPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
+        let cache = TiledResourceCache::default();
         app.add_plugin(TilemapPlugin)
             .add_asset::<TiledMap>()
-            .add_asset_loader(TiledLoader)
-            .add_system(process_loaded_maps);
+            .add_asset::<TiledDependency>()
+            .add_asset_loader(TiledLoader {
+                cache: cache.clone(),
+            })
+            .init_asset_loader::<TiledDependencyLoader>()
+            .insert_resource(cache)
+            .add_event::<MapLoaded>()
+            .add_event::<MapUnloaded>()
+            .init_resource::<TiledObjectRegistry>()
+            .init_resource::<TileAnimationClock>()
+            .init_resource::<TiledMapSettings>()
+            .add_system(process_loaded_maps)
+            .add_system(stream_map_chunks.after(process_loaded_maps))
+            .add_system(reload_dependent_maps)
+            .add_system(prune_resource_cache)
+            .add_system(animate_tiles)
+            .add_system(cover_repeated_images)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                apply_parallax.before(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -26,13 +64,46 @@ impl Plugin for TiledMapPlugin {
 #[uuid = "7b4b2475-9a8d-4676-b8b2-06639ff9511a"]
 pub struct TiledMap {
     pub map: tiled::Map,
-    pub tilesets: HashMap<String, Handle<Image>>,
+    /// Textures keyed by tileset index; tilesets without a single image are absent.
+    pub tilesets: HashMap<usize, Handle<Image>>,
+    /// Atlases of the tileset textures, keyed by tileset index. Only built for
+    /// isometric, staggered and hexagonal maps, whose tiles are drawn as sprites.
+    pub atlases: HashMap<usize, Handle<TextureAtlas>>,
+    /// Textures of the image layers, keyed by image path.
+    pub images: HashMap<PathBuf, Handle<Image>>,
+    /// External tilesets and templates the map was built from.
+    pub dependencies: Vec<PathBuf>,
 }
 
-// Stores a list of tiled layers.
+/// Depth between the tilemaps of one layer that use different tilesets.
+const TILESET_Z_STEP: f32 = 0.01;
+
+// Stores the tilemaps spawned for the tiled layers, keyed by (layer index, tileset index).
 #[derive(Component, Default)]
 pub struct TiledLayersStorage {
-    pub storage: HashMap<u32, Entity>,
+    pub storage: HashMap<(u32, usize), Entity>,
+    /// Entities spawned for each chunk of an infinite map currently streamed in,
+    /// keyed by chunk coordinates.
+    pub chunks: HashMap<IVec2, Vec<Entity>>,
+    /// Whether the map's entities are currently spawned under the map entity.
+    loaded: bool,
+}
+
+/// Sent once a map has spawned its layers, tiles, colliders and objects as
+/// children of its entity. They exist from the next stage on.
+#[derive(Debug, Clone)]
+pub struct MapLoaded {
+    pub entity: Entity,
+    pub map: Handle<TiledMap>,
+}
+
+/// Sent when the children of a map entity are despawned, because the map is
+/// about to be rebuilt (hot reload, new handle) or its asset was removed.
+/// Whoever despawns a loaded map entity sends it too.
+#[derive(Debug, Clone)]
+pub struct MapUnloaded {
+    pub entity: Entity,
+    pub map: Handle<TiledMap>,
 }
 
 #[derive(Default, Bundle)]
@@ -43,7 +114,54 @@ pub struct TiledMapBundle {
     pub global_transform: GlobalTransform,
 }
 
-pub struct TiledLoader;
+/// Converts Tiled map coordinates (pixels, y down, origin at the top-left corner)
+/// into the space of the map entity, whose origin sits at the centre of the map.
+#[derive(Clone, Copy, Debug)]
+pub struct TiledMapSpace {
+    /// Map size in pixels.
+    pub size: Vec2,
+    /// Transform of the map entity.
+    pub transform: Transform,
+    /// Where the map's orientation places tiles and objects.
+    pub projection: TiledProjection,
+}
+
+impl TiledMapSpace {
+    pub fn new(map: &tiled::Map, transform: Transform) -> Self {
+        let projection = TiledProjection::new(map);
+        Self {
+            size: projection.map_size(),
+            transform,
+            projection,
+        }
+    }
+
+    /// Position of map point (x, y) relative to the map entity.
+    pub fn to_local(&self, x: f32, y: f32) -> Vec2 {
+        Vec2::new(x - self.size.x / 2.0, self.size.y / 2.0 - y)
+    }
+
+    /// World transform of something placed at `local` (see [`TiledMapSpace::to_local`]).
+    pub fn to_world(&self, local: Vec2, z: f32) -> Transform {
+        self.transform * Transform::from_translation(local.extend(z))
+    }
+
+    /// Position of an object placed at (x, y) in a layer with the given offset,
+    /// relative to the map entity.
+    pub fn object_to_local(&self, x: f32, y: f32, offset: Vec2) -> Vec2 {
+        let pixel = offset + self.projection.object_to_pixel(Vec2::new(x, y));
+        self.to_local(pixel.x, pixel.y)
+    }
+
+    /// Map point at `local`, the inverse of [`TiledMapSpace::to_local`].
+    pub fn to_map(&self, local: Vec2) -> Vec2 {
+        Vec2::new(local.x + self.size.x / 2.0, self.size.y / 2.0 - local.y)
+    }
+}
+
+pub struct TiledLoader {
+    cache: TiledResourceCache,
+}
 
 impl AssetLoader for TiledLoader {
     fn load<'a>(
@@ -52,22 +170,38 @@ impl AssetLoader for TiledLoader {
         load_context: &'a mut bevy::asset::LoadContext,
     ) -> bevy::asset::BoxedFuture<'a, anyhow::Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let root_dir = load_context.path().parent().unwrap();
-
-            let mut loader = tiled::Loader::new();
-            let map = loader.load_tmx_map_from(BufReader::new(bytes), root_dir)?;
+            // JSON maps are rewritten into TMX and parsed the same way.
+            let tmx;
+            let bytes = match load_context.path().extension().and_then(|it| it.to_str()) {
+                Some("tmx") => bytes,
+                _ => {
+                    tmx = json_to_tmx(bytes)?;
+                    tmx.as_bytes()
+                }
+            };
 
-            // let map = tiled::parse(BufReader::new(bytes))?;
+            let (map, map_dependencies) = load_tmx_map(load_context, &self.cache, bytes).await?;
 
-            let mut dependencies = Vec::new();
+            let mut dependencies: Vec<AssetPath> = map_dependencies
+                .iter()
+                .map(|path| AssetPath::new(path.clone(), None))
+                .collect();
             let mut handles = HashMap::default();
+            let mut atlases = HashMap::default();
 
-            for tileset in map.tilesets() {
+            for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
                 let image_path = match &tileset.image {
                     Some(img) => img,
-                    None => continue,
+                    None => {
+                        warn!(
+                            "tileset {:?} has no single image, its tiles are skipped",
+                            tileset.name
+                        );
+                        continue;
+                    }
                 };
-                let tile_path = root_dir.join(image_path.source.clone());
+                // Image paths come out relative to the asset root, like the map path.
+                let tile_path = normalize_path(&image_path.source);
 
                 info!("load file: {:?}", tile_path);
 
@@ -77,16 +211,34 @@ impl AssetLoader for TiledLoader {
                 // for i in tileset.first_gid..(tileset.first_gid + tileset.tilecount.unwrap_or(1)) {
 
                 // }
-                handles.insert(tileset.name.clone(), texture.clone());
+                handles.insert(tileset_index, texture.clone());
+                if map.orientation != tiled::Orientation::Orthogonal {
+                    let atlas = tileset_atlas(tileset, image_path, texture.clone());
+                    let label = format!("atlas{}", tileset_index);
+                    atlases.insert(
+                        tileset_index,
+                        load_context.set_labeled_asset(&label, LoadedAsset::new(atlas)),
+                    );
+                }
 
                 info!("load file: {:?}", handles);
 
                 dependencies.push(asset_path);
             }
 
+            let mut images = HashMap::default();
+            for image_path in image_layer_sources(map.layers()) {
+                let asset_path = AssetPath::new(normalize_path(&image_path), None);
+                images.insert(image_path, load_context.get_handle(asset_path.clone()));
+                dependencies.push(asset_path);
+            }
+
             let loaded_asset = LoadedAsset::new(TiledMap {
                 map,
                 tilesets: handles,
+                atlases,
+                images,
+                dependencies: map_dependencies,
             });
             load_context.set_default_asset(loaded_asset.with_dependencies(dependencies));
             Ok(())
@@ -94,30 +246,59 @@ impl AssetLoader for TiledLoader {
     }
 
     fn extensions(&self) -> &[&str] {
-        static EXTENSIONS: &[&str] = &["tmx"];
+        // `.json` only gets the files no loader with a longer extension, such
+        // as `.anim_ske.json`, claims.
+        static EXTENSIONS: &[&str] = &["tmx", "tmj", "json"];
         EXTENSIONS
     }
 }
 
+/// Images of the image layers, including those nested in groups.
+fn image_layer_sources<'map>(layers: impl Iterator<Item = tiled::Layer<'map>>) -> Vec<PathBuf> {
+    let mut sources = Vec::new();
+    for layer in layers {
+        match layer.layer_type() {
+            tiled::LayerType::Image(image_layer) => {
+                if let Some(image) = &image_layer.image {
+                    sources.push(image.source.clone());
+                }
+            }
+            tiled::LayerType::Group(group) => sources.extend(image_layer_sources(group.layers())),
+            _ => {}
+        }
+    }
+    sources
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn process_loaded_maps(
     mut commands: Commands,
     mut map_events: EventReader<AssetEvent<TiledMap>>,
+    mut loaded_events: EventWriter<MapLoaded>,
+    mut unloaded_events: EventWriter<MapUnloaded>,
     maps: Res<Assets<TiledMap>>,
-    tile_storage_query: Query<(Entity, &TileStorage)>,
-    mut map_query: Query<(&Handle<TiledMap>, &mut TiledLayersStorage)>,
-    new_maps: Query<&Handle<TiledMap>, Added<Handle<TiledMap>>>,
+    object_registry: Res<TiledObjectRegistry>,
+    settings: Res<TiledMapSettings>,
+    mut map_query: Query<(
+        Entity,
+        &Handle<TiledMap>,
+        ChangeTrackers<Handle<TiledMap>>,
+        &mut TiledLayersStorage,
+        Option<&Children>,
+    )>,
 ) {
     let mut changed_maps = Vec::<Handle<TiledMap>>::default();
+    let mut removed_maps = Vec::<Handle<TiledMap>>::default();
     for event in map_events.iter() {
         info!("load event: {:?}", event);
         match event {
             AssetEvent::Created { handle } => {
                 info!("Map added!");
-                changed_maps.push(handle.clone());
+                changed_maps.push(handle.clone_weak());
             }
             AssetEvent::Modified { handle } => {
                 info!("Map changed!");
-                changed_maps.push(handle.clone());
+                changed_maps.push(handle.clone_weak());
             }
             AssetEvent::Removed { handle } => {
                 info!("Map removed!");
@@ -125,186 +306,467 @@ pub fn process_loaded_maps(
                 // events are ordered so future modification events are ok
                 changed_maps = changed_maps
                     .into_iter()
-                    .filter(|changed_handle| changed_handle == handle)
+                    .filter(|changed_handle| changed_handle != handle)
                     .collect();
+                removed_maps.push(handle.clone_weak());
             }
         }
     }
 
-    // If we have new map entities add them to the changed_maps list.
-    for new_map_handle in new_maps.iter() {
-        changed_maps.push(new_map_handle.clone_weak());
-    }
+    for (map_entity, map_handle, handle_tracker, mut layer_storage, children) in
+        map_query.iter_mut()
+    {
+        // New map entities, or entities pointed at another map, are (re)built as well.
+        let changed = handle_tracker.is_changed() || changed_maps.contains(map_handle);
+        if !changed && !removed_maps.contains(map_handle) {
+            continue;
+        }
 
-    for changed_map in changed_maps.iter() {
-        for (map_handle, mut layer_storage) in map_query.iter_mut() {
-            // only deal with currently changed map
-            if map_handle != changed_map {
-                continue;
+        // Everything spawned for the previous version of the map hangs under the map entity.
+        if layer_storage.loaded {
+            if let Some(children) = children {
+                for child in children.iter() {
+                    commands.entity(*child).despawn_recursive();
+                }
             }
-            if let Some(tiled_map) = maps.get(map_handle) {
-                // for (name, img) in tiled_map.tilesets.iter() {
-                //     info!("name: {}", name);
-                //     commands.spawn_bundle(SpriteBundle {
-                //         texture: img.clone(),
-                //         ..default()
-                //     });
-                // }
+            layer_storage.storage.clear();
+            layer_storage.chunks.clear();
+            layer_storage.loaded = false;
+            commands.entity(map_entity).remove::<TiledProperties>();
+            unloaded_events.send(MapUnloaded {
+                entity: map_entity,
+                map: map_handle.clone_weak(),
+            });
+        }
 
-                // TODO: Create a RemoveMap component..
-                for layer_entity in layer_storage.storage.values() {
-                    if let Ok((_, layer_tile_storage)) = tile_storage_query.get(*layer_entity) {
-                        for tile in layer_tile_storage.iter() {
-                            if let Some(tile) = tile {
-                                commands.entity(*tile).despawn_recursive()
+        if !changed {
+            continue;
+        }
+        if let Some(tiled_map) = maps.get(map_handle) {
+            // Spawned entities are children of the map, so they are placed relative to it.
+            let space = TiledMapSpace::new(&tiled_map.map, Transform::identity());
+            let mut spawned = Vec::new();
+
+            // Group layers are flattened, their children inheriting offsets, opacity,
+            // tint, visibility and parallax.
+            for (layer_index, (layer, context)) in
+                flatten_layers(&tiled_map.map).into_iter().enumerate()
+            {
+                let z = settings.layer_z(layer_index, &layer);
+                let render = context.visible || settings.spawn_hidden_layers;
+
+                let tile_layer = match layer.layer_type() {
+                    tiled::LayerType::Tiles(v) => v,
+                    tiled::LayerType::Image(v) => {
+                        if !render {
+                            continue;
+                        }
+                        let entity = spawn_image_layer(
+                            &mut commands,
+                            tiled_map,
+                            &space,
+                            &layer,
+                            &v,
+                            &context,
+                            z,
+                        );
+                        if let Some(entity) = entity {
+                            if let Some(properties) = TiledProperties::non_empty(&layer.properties)
+                            {
+                                commands.entity(entity).insert(properties);
                             }
+                            spawned.push(entity);
                         }
+                        continue;
                     }
-                    // commands.entity(*layer_entity).despawn_recursive();
-                }
-
-                for tileset in tiled_map.map.tilesets() {
-                    // Once materials have been created/added we need to then create the layers.
-                    for (layer_index, layer) in tiled_map.map.layers().enumerate() {
-                        let tile_size = TilemapTileSize {
-                            x: tileset.tile_width as f32,
-                            y: tileset.tile_height as f32,
-                        };
-                        let tile_spacing = TilemapSpacing {
-                            x: tileset.spacing as f32,
-                            y: tileset.spacing as f32,
-                        };
-
-                        let offset_x = layer.offset_x;
-                        let offset_y = layer.offset_y;
-
-                        let map_size = TilemapSize {
-                            x: tiled_map.map.width,
-                            y: tiled_map.map.height,
-                        };
-
-                        let grid_size = TilemapGridSize {
-                            x: tiled_map.map.tile_width as f32,
-                            y: tiled_map.map.tile_height as f32,
-                        };
-
-                        let mesh_type = match tiled_map.map.orientation {
-                            tiled::Orientation::Hexagonal => TilemapMeshType::Hexagon(HexType::Row),
-                            tiled::Orientation::Isometric => {
-                                TilemapMeshType::Isometric(IsoType::Diamond)
+                    tiled::LayerType::Objects(v) => {
+                        for obj in v.object_data() {
+                            let shape = space.projection.project_shape(&obj.shape);
+                            let properties = TiledProperties(obj.properties.clone());
+                            let object = TiledObject {
+                                id: obj.id(),
+                                name: &obj.name,
+                                user_type: &obj.user_type,
+                                shape: &shape,
+                                position: space.object_to_local(obj.x, obj.y, context.offset),
+                                rotation: obj.rotation,
+                                properties: &properties,
+                            };
+                            // Objects share their layer's depth, so characters spawned
+                            // from them are drawn between the layers around it.
+                            let transform = TransformBundle::from(
+                                space.to_world(object.center(), z)
+                                    * Transform::from_rotation(object.rotation()),
+                            );
+
+                            let spawner = object_registry.get(object.user_type);
+                            if spawner.is_none() && !object.user_type.is_empty() {
+                                warn!(
+                                    "no spawner registered for tiled object type {:?}",
+                                    object.user_type
+                                );
                             }
-                            tiled::Orientation::Staggered => {
-                                TilemapMeshType::Isometric(IsoType::Staggered)
-                            }
-                            tiled::Orientation::Orthogonal => TilemapMeshType::Square,
-                        };
-
-                        let mut tile_storage = TileStorage::empty(map_size);
-                        let layer_entity = commands.spawn();
-                        let layer_entity_id = layer_entity.id();
-
-                        let tile_layer = match layer.layer_type() {
-                            tiled::LayerType::TileLayer(v) => v,
-                            tiled::LayerType::ObjectLayer(v) => {
-                                for obj in v.object_data() {
-                                    match obj.shape.clone() {
-                                        tiled::ObjectShape::Rect { width, height } => {
-                                            commands
-                                                .spawn()
-                                                .insert(Collider::cuboid(width / 2.0, height / 2.0))
-                                                .insert_bundle(TransformBundle::from(
-                                                    Transform::from_xyz(
-                                                        obj.x - 800.0 + width / 2.0,
-                                                        480.0 - obj.y - height / 2.0,
-                                                        0.0,
-                                                    ),
-                                                ));
-                                        }
-                                        tiled::ObjectShape::Polygon { points } => {
-                                            let mut ps = Vec::new();
-                                            for (px, py) in points {
-                                                ps.push(Vect::new(px + obj.x - 800.0, 480.0 - py - obj.y));
-                                            }
-                                            commands.spawn().insert(Collider::polyline(ps, None));
-                                            // .insert_bundle(TransformBundle::from(Transform::from_xyz(obj.x - 800.0 + width / 2.0,  480.0 - obj.y - height / 2.0, 0.0)));
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                continue;
-                            }
-                            _ => {
+                            let collider = object.collider();
+                            let point = matches!(object.shape, tiled::ObjectShape::Point(..));
+                            // Text objects and the like have nothing to spawn.
+                            if spawner.is_none() && collider.is_none() && !point {
                                 continue;
                             }
-                        };
 
-                        for x in 0..map_size.x {
-                            for y in 0..map_size.y {
-                                let mut mapped_y = x;
-                                if tiled_map.map.orientation == tiled::Orientation::Orthogonal {
-                                    mapped_y = (tiled_map.map.height - 1) as u32 - y;
+                            let mut entity = commands.spawn();
+                            entity.insert_bundle(transform);
+                            if !properties.0.is_empty() {
+                                entity.insert(properties.clone());
+                            }
+                            if let Some(spawner) = spawner {
+                                spawner(&mut entity, &object);
+                            } else if let Some(collider) = collider {
+                                entity.insert(collider);
+                                // Flagged with a bool property `one_way`.
+                                if object.properties.bool("one_way") == Some(true) {
+                                    entity.insert_bundle(OneWayPlatformBundle::default());
                                 }
-
-                                // let mapped_x = x as usize;
-                                // let mapped_y = mapped_y as usize;
-
-                                let map_tile = match tile_layer.get_tile(x as i32, mapped_y as i32)
-                                {
-                                    Some(v) => v,
-                                    None => continue,
-                                };
-
-                                // let tile_id = map_tile.gid - tileset.first_gid;
-
-                                let tile_pos = TilePos { x, y };
-
-                                let tile_entity = commands
-                                    .spawn()
-                                    .insert_bundle(TileBundle {
-                                        position: tile_pos,
-                                        tilemap_id: TilemapId(layer_entity_id),
-                                        texture: TileTexture(map_tile.id()),
-                                        flip: TileFlip {
-                                            x: map_tile.flip_h,
-                                            y: map_tile.flip_v,
-                                            d: map_tile.flip_d,
-                                        },
-                                        ..Default::default()
-                                    })
-                                    .id();
-
-                                tile_storage.set(&tile_pos, Some(tile_entity));
+                            } else {
+                                entity.insert(TiledPoint {
+                                    id: object.id,
+                                    name: object.name.to_string(),
+                                });
                             }
+                            spawned.push(entity.id());
                         }
+                        continue;
+                    }
+                    _ => {
+                        continue;
+                    }
+                };
+
+                // Infinite maps are streamed in chunks around the camera instead.
+                if tiled_map.map.infinite() {
+                    continue;
+                }
+                let region = TileRegion {
+                    x: 0,
+                    y: 0,
+                    width: tiled_map.map.width,
+                    height: tiled_map.map.height,
+                };
+                let (tilemaps, colliders) = spawn_tile_region(
+                    &mut commands,
+                    tiled_map,
+                    &space,
+                    &tile_layer,
+                    &context,
+                    z,
+                    render,
+                    region,
+                );
+                spawned.extend(colliders);
+                for (tileset_index, tilemap_entity) in tilemaps {
+                    if let Some(properties) = TiledProperties::non_empty(&layer.properties) {
+                        commands.entity(tilemap_entity).insert(properties);
+                    }
+                    spawned.push(tilemap_entity);
+                    layer_storage
+                        .storage
+                        .insert((layer_index as u32, tileset_index), tilemap_entity);
+                }
+            }
+
+            commands
+                .entity(map_entity)
+                .insert(TiledProperties(tiled_map.map.properties.clone()))
+                .push_children(&spawned);
+            layer_storage.loaded = true;
+            loaded_events.send(MapLoaded {
+                entity: map_entity,
+                map: map_handle.clone_weak(),
+            });
+        }
+    }
+}
+
+/// A rectangle of the Tiled grid, in tiles.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct TileRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Spawns the tiles of `region` as one tilemap per tileset, plus the colliders
+/// drawn on them. Returns the tilemaps keyed by tileset index, and the colliders.
+///
+/// Only orthogonal maps use tilemaps: the other orientations are drawn as
+/// sprites laid out like Tiled does. Their tile collision is placed the same
+/// way, one collider per shape, without merging neighbouring solid tiles.
+#[allow(clippy::too_many_arguments)]
+fn spawn_tile_region(
+    commands: &mut Commands,
+    tiled_map: &TiledMap,
+    space: &TiledMapSpace,
+    tile_layer: &tiled::TileLayer,
+    context: &TiledLayerContext,
+    z: f32,
+    render: bool,
+    region: TileRegion,
+) -> (HashMap<usize, Entity>, Vec<Entity>) {
+    let map = &tiled_map.map;
+    // Collision drawn on the tiles themselves.
+    let mut colliders = TileColliders::new(&space.projection, region);
+    // Top-left corner of the region's first cell, in map pixels.
+    let origin = context.offset + space.projection.cell_origin(region.x, region.y);
+
+    if map.orientation != tiled::Orientation::Orthogonal {
+        for y in 0..region.height {
+            for x in 0..region.width {
+                if let Some(map_tile) =
+                    tile_layer.get_tile(region.x + x as i32, region.y + y as i32)
+                {
+                    colliders.add_tile(x, y, &map_tile);
+                }
+            }
+        }
+        let sprites = spawn_tile_sprites(
+            commands, tiled_map, space, tile_layer, context, z, render, region,
+        );
+        return (sprites, colliders.spawn(commands, space, origin));
+    }
+
+    let size = TilemapSize {
+        x: region.width,
+        y: region.height,
+    };
+
+    let grid_size = TilemapGridSize {
+        x: map.tile_width as f32,
+        y: map.tile_height as f32,
+    };
+
+    // A layer may mix tiles of several tilesets, but a tilemap has a single
+    // texture: group the tiles by tileset and give each group its own tilemap.
+    let mut tilemaps: HashMap<usize, (Entity, TileStorage)> = HashMap::default();
+
+    for x in 0..size.x {
+        for y in 0..size.y {
+            // Tilemap rows count upwards, Tiled rows downwards.
+            let mapped_y = size.y - 1 - y;
+
+            let map_tile =
+                match tile_layer.get_tile(region.x + x as i32, region.y + mapped_y as i32) {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+            colliders.add_tile(x, mapped_y, &map_tile);
+            if !render {
+                continue;
+            }
+
+            // The tile's gid is already resolved into a tileset index and
+            // an id local to that tileset.
+            let tileset_index = map_tile.tileset_index();
+            if !tiled_map.tilesets.contains_key(&tileset_index) {
+                continue;
+            }
+            let (tilemap_entity, tile_storage) = tilemaps
+                .entry(tileset_index)
+                .or_insert_with(|| (commands.spawn().id(), TileStorage::empty(size)));
+
+            let tile_pos = TilePos { x, y };
+
+            let tile_entity = commands
+                .spawn()
+                .insert_bundle(TileBundle {
+                    position: tile_pos,
+                    tilemap_id: TilemapId(*tilemap_entity),
+                    texture: TileTexture(map_tile.id()),
+                    flip: TileFlip {
+                        x: map_tile.flip_h,
+                        y: map_tile.flip_v,
+                        d: map_tile.flip_d,
+                    },
+                    color: TileColor(context.color()),
+                    ..Default::default()
+                })
+                .id();
+
+            insert_tile_components(&mut commands.entity(tile_entity), &map_tile);
+
+            tile_storage.set(&tile_pos, Some(tile_entity));
+        }
+    }
 
-                        let tilemap_bundle = TilemapBundle {
-                            grid_size,
-                            size: map_size,
-                            storage: tile_storage,
-                            texture: TilemapTexture(
-                                tiled_map.tilesets.get(&tileset.name).unwrap().clone_weak(),
-                            ),
-                            tile_size,
-                            spacing: tile_spacing,
-                            transform: bevy_ecs_tilemap::helpers::get_centered_transform_2d(
-                                &map_size,
-                                &tile_size,
-                                layer_index as f32,
-                            ) * Transform::from_xyz(offset_x, -offset_y, 0.0),
-                            mesh_type,
-                            ..Default::default()
-                        };
-
-                        commands
-                            .entity(layer_entity_id)
-                            .insert_bundle(tilemap_bundle);
-
-                        layer_storage
-                            .storage
-                            .insert(layer_index as u32, layer_entity_id);
+    let colliders = colliders.spawn(commands, space, origin);
+
+    let mut spawned = HashMap::default();
+    for (tileset_index, (tilemap_entity, tile_storage)) in tilemaps {
+        let tileset = &map.tilesets()[tileset_index];
+        let tiles: Vec<Entity> = tile_storage.iter().flatten().copied().collect();
+
+        // Tilemaps are placed by their bottom-left corner.
+        let bottom_left = space.to_local(origin.x, origin.y + size.y as f32 * grid_size.y);
+        let tilemap_bundle = TilemapBundle {
+            grid_size,
+            size,
+            storage: tile_storage,
+            texture: TilemapTexture(tiled_map.tilesets[&tileset_index].clone_weak()),
+            tile_size: TilemapTileSize {
+                x: tileset.tile_width as f32,
+                y: tileset.tile_height as f32,
+            },
+            spacing: TilemapSpacing {
+                x: tileset.spacing as f32,
+                y: tileset.spacing as f32,
+            },
+            transform: space.to_world(bottom_left, z + tileset_index as f32 * TILESET_Z_STEP),
+            visibility: Visibility {
+                is_visible: context.visible,
+            },
+            mesh_type: TilemapMeshType::Square,
+            ..Default::default()
+        };
+
+        let parallax = TiledParallax::new(context, tilemap_bundle.transform.translation, space);
+        commands
+            .entity(tilemap_entity)
+            .insert_bundle(tilemap_bundle)
+            .push_children(&tiles);
+        if !parallax.is_static() {
+            commands.entity(tilemap_entity).insert(parallax);
+        }
+        spawned.insert(tileset_index, tilemap_entity);
+    }
+    (spawned, colliders)
+}
+
+/// Adds what the tileset defines for a tile: its animation and custom properties.
+pub(super) fn insert_tile_components(entity: &mut EntityCommands, map_tile: &tiled::LayerTile) {
+    let tile = match map_tile.get_tile() {
+        Some(v) => v,
+        None => return,
+    };
+    if let Some(animation) = tile.animation.as_deref().and_then(TiledTileAnimation::new) {
+        entity.insert(animation);
+    }
+    if let Some(properties) = TiledProperties::non_empty(&tile.properties) {
+        entity.insert(properties);
+    }
+}
+
+/// Spawns the chunks of infinite maps that come within
+/// [`TiledMapSettings::chunk_radius`] of the active camera, and despawns the
+/// ones that leave it.
+pub fn stream_map_chunks(
+    mut commands: Commands,
+    maps: Res<Assets<TiledMap>>,
+    settings: Res<TiledMapSettings>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut map_query: Query<(
+        Entity,
+        &Handle<TiledMap>,
+        &GlobalTransform,
+        &mut TiledLayersStorage,
+    )>,
+) {
+    let camera = match cameras.iter().find(|(camera, _)| camera.is_active) {
+        Some((_, transform)) => transform.translation(),
+        None => return,
+    };
+
+    for (map_entity, map_handle, map_transform, mut layer_storage) in &mut map_query {
+        if !layer_storage.loaded {
+            continue;
+        }
+        let tiled_map = match maps.get(map_handle) {
+            Some(v) if v.map.infinite() => v,
+            _ => continue,
+        };
+        let space = TiledMapSpace::new(&tiled_map.map, Transform::identity());
+        let view = map_transform
+            .affine()
+            .inverse()
+            .transform_point3(camera)
+            .truncate();
+        let view = space.to_map(view);
+
+        // Cells under the corners of the square around the view; on isometric
+        // maps it covers a rotated area of the grid.
+        let radius = settings.chunk_radius;
+        let corners = [
+            Vec2::new(-radius, -radius),
+            Vec2::new(radius, -radius),
+            Vec2::new(-radius, radius),
+            Vec2::new(radius, radius),
+        ]
+        .map(|corner| space.projection.cell_at(view + corner));
+        let chunk_size = Vec2::new(
+            tiled::ChunkData::WIDTH as f32,
+            tiled::ChunkData::HEIGHT as f32,
+        );
+        let min = (corners.into_iter().reduce(Vec2::min).unwrap() / chunk_size)
+            .floor()
+            .as_ivec2();
+        let max = (corners.into_iter().reduce(Vec2::max).unwrap() / chunk_size)
+            .floor()
+            .as_ivec2();
+
+        let far: Vec<IVec2> = layer_storage
+            .chunks
+            .keys()
+            .filter(|chunk| chunk.cmplt(min).any() || chunk.cmpgt(max).any())
+            .copied()
+            .collect();
+        for chunk in far {
+            for entity in layer_storage.chunks.remove(&chunk).unwrap_or_default() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let missing: Vec<IVec2> = (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter(|chunk| !layer_storage.chunks.contains_key(chunk))
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+
+        let layers = flatten_layers(&tiled_map.map);
+        for chunk in missing {
+            let region = TileRegion {
+                x: chunk.x * tiled::ChunkData::WIDTH as i32,
+                y: chunk.y * tiled::ChunkData::HEIGHT as i32,
+                width: tiled::ChunkData::WIDTH,
+                height: tiled::ChunkData::HEIGHT,
+            };
+            let mut spawned = Vec::new();
+            for (layer_index, (layer, context)) in layers.iter().enumerate() {
+                let tile_layer = match layer.layer_type() {
+                    tiled::LayerType::Tiles(v) => v,
+                    _ => continue,
+                };
+                let (tilemaps, colliders) = spawn_tile_region(
+                    &mut commands,
+                    tiled_map,
+                    &space,
+                    &tile_layer,
+                    context,
+                    settings.layer_z(layer_index, layer),
+                    context.visible || settings.spawn_hidden_layers,
+                    region,
+                );
+                for tilemap_entity in tilemaps.into_values() {
+                    if let Some(properties) = TiledProperties::non_empty(&layer.properties) {
+                        commands.entity(tilemap_entity).insert(properties);
                     }
+                    spawned.push(tilemap_entity);
                 }
+                spawned.extend(colliders);
             }
+            commands.entity(map_entity).push_children(&spawned);
+            // Empty chunks are remembered too, so they aren't looked up every frame.
+            layer_storage.chunks.insert(chunk, spawned);
         }
     }
 }
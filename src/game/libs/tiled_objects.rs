@@ -0,0 +1,129 @@
+use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::{Collider, Vect};
+
+use super::tiled_properties::TiledProperties;
+
+/// Builds the gameplay side of a Tiled object. The entity is already spawned
+/// as a child of the map entity, with a `TransformBundle` placed at [`TiledObject::center`]
+/// and the object's `TiledProperties` if it has any.
+pub type TiledObjectSpawner = Box<dyn Fn(&mut EntityCommands, &TiledObject) + Send + Sync>;
+
+/// Spawners keyed by the Tiled object type ("Class" in newer Tiled versions).
+/// Objects without a registered type keep becoming plain colliders.
+#[derive(Default)]
+pub struct TiledObjectRegistry {
+    spawners: HashMap<String, TiledObjectSpawner>,
+}
+
+impl TiledObjectRegistry {
+    pub fn register(
+        &mut self,
+        user_type: impl Into<String>,
+        spawner: impl Fn(&mut EntityCommands, &TiledObject) + Send + Sync + 'static,
+    ) {
+        self.spawners.insert(user_type.into(), Box::new(spawner));
+    }
+
+    pub fn get(&self, user_type: &str) -> Option<&TiledObjectSpawner> {
+        self.spawners.get(user_type)
+    }
+}
+
+pub trait TiledObjectAppExt {
+    fn register_tiled_object(
+        &mut self,
+        user_type: &str,
+        spawner: impl Fn(&mut EntityCommands, &TiledObject) + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl TiledObjectAppExt for App {
+    fn register_tiled_object(
+        &mut self,
+        user_type: &str,
+        spawner: impl Fn(&mut EntityCommands, &TiledObject) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.init_resource::<TiledObjectRegistry>();
+        self.world
+            .resource_mut::<TiledObjectRegistry>()
+            .register(user_type, spawner);
+        self
+    }
+}
+
+/// Point objects without a registered type, kept as markers in the world.
+#[derive(Component, Debug, Clone)]
+pub struct TiledPoint {
+    pub id: u32,
+    pub name: String,
+}
+
+/// One object of a Tiled object layer, as handed to a [`TiledObjectSpawner`].
+pub struct TiledObject<'a> {
+    pub id: u32,
+    pub name: &'a str,
+    pub user_type: &'a str,
+    /// Shape in map pixels, already projected on isometric maps, where
+    /// rectangles and ellipses become polygons (see `TiledProjection::project_shape`).
+    pub shape: &'a tiled::ObjectShape,
+    /// Position of the object's origin relative to the map entity
+    /// (see `TiledMapSpace`); Tiled puts it at the top-left of rectangles.
+    pub position: Vec2,
+    /// Clockwise rotation around the origin, in degrees, as shown by Tiled.
+    pub rotation: f32,
+    pub properties: &'a TiledProperties,
+}
+
+impl<'a> TiledObject<'a> {
+    /// Centre of rectangles and ellipses, the origin for every other shape.
+    pub fn center(&self) -> Vec2 {
+        match self.shape {
+            tiled::ObjectShape::Rect { width, height }
+            | tiled::ObjectShape::Ellipse { width, height } => {
+                self.position
+                    + Mat2::from_angle(-self.rotation.to_radians())
+                        * Vec2::new(width / 2.0, -height / 2.0)
+            }
+            _ => self.position,
+        }
+    }
+
+    /// Rotation of the object around [`TiledObject::center`].
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_z(-self.rotation.to_radians())
+    }
+
+    /// Collider for the object's shape, relative to [`TiledObject::center`]
+    /// and [`TiledObject::rotation`]. Points have none.
+    pub fn collider(&self) -> Option<Collider> {
+        match self.shape {
+            tiled::ObjectShape::Rect { width, height } => {
+                Some(Collider::cuboid(width / 2.0, height / 2.0))
+            }
+            tiled::ObjectShape::Ellipse { width, height } => {
+                let (width, height) = (*width, *height);
+                if width > height {
+                    Some(Collider::capsule_x((width - height) / 2.0, height / 2.0))
+                } else if height > width {
+                    Some(Collider::capsule_y((height - width) / 2.0, width / 2.0))
+                } else {
+                    Some(Collider::ball(width / 2.0))
+                }
+            }
+            tiled::ObjectShape::Polyline { points } => {
+                Some(Collider::polyline(vertices(points), None))
+            }
+            tiled::ObjectShape::Polygon { points } if points.len() >= 3 => {
+                let count = points.len() as u32;
+                let indices: Vec<[u32; 2]> = (0..count).map(|i| [i, (i + 1) % count]).collect();
+                Some(Collider::convex_decomposition(&vertices(points), &indices))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Tiled points (y down) as collider vertices (y up).
+fn vertices(points: &[(f32, f32)]) -> Vec<Vect> {
+    points.iter().map(|(x, y)| Vect::new(*x, -*y)).collect()
+}
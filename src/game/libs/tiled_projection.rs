@@ -0,0 +1,549 @@
+//! Placement of tiles and objects for each of Tiled's map orientations.
+
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{prelude::*, sprite::Rect, utils::HashMap};
+
+use super::{
+    tiled_collision::ellipse_points,
+    tiled_layers::{TiledLayerContext, TiledParallax},
+    tiled_map::{insert_tile_components, TileRegion, TiledMap, TiledMapSpace},
+};
+
+/// Depth added per map pixel to sprite tiles, so that lower tiles are drawn
+/// over the ones above them. Keeps a layer within a depth of 1 for maps up to
+/// 10000 pixels tall.
+const TILE_SORT_STEP: f32 = 0.0001;
+
+/// Tiled's layout of a map's tile grid, in map pixels (y down, origin at the
+/// top-left corner of the map's bounding box).
+#[derive(Clone, Copy, Debug)]
+pub struct TiledProjection {
+    pub orientation: tiled::Orientation,
+    /// Map size in tiles.
+    pub size: UVec2,
+    /// Size of a grid cell in pixels.
+    pub tile_size: Vec2,
+    /// Whether columns rather than rows are staggered (staggered and hexagonal maps).
+    pub stagger_x: bool,
+    /// Whether the odd rather than the even rows or columns are shifted.
+    pub stagger_odd: bool,
+    /// Length of the flat side of hexagonal tiles, 0 for other orientations.
+    pub side_length: f32,
+}
+
+impl TiledProjection {
+    pub fn new(map: &tiled::Map) -> Self {
+        Self {
+            orientation: map.orientation,
+            size: UVec2::new(map.width, map.height),
+            tile_size: Vec2::new(map.tile_width as f32, map.tile_height as f32),
+            stagger_x: matches!(map.stagger_axis, tiled::StaggerAxis::X),
+            stagger_odd: matches!(map.stagger_index, tiled::StaggerIndex::Odd),
+            side_length: match map.orientation {
+                tiled::Orientation::Hexagonal => map.hex_side_length as f32,
+                _ => 0.0,
+            },
+        }
+    }
+
+    /// Size of the map's bounding box in pixels.
+    pub fn map_size(&self) -> Vec2 {
+        let size = self.size.as_vec2();
+        let tile = self.tile_size;
+        match self.orientation {
+            tiled::Orientation::Orthogonal => size * tile,
+            tiled::Orientation::Isometric => (size.x + size.y) * tile / 2.0,
+            tiled::Orientation::Staggered | tiled::Orientation::Hexagonal => {
+                // Staggered maps are hexagonal maps whose tiles have no flat side.
+                let side = self.side_length;
+                if self.stagger_x {
+                    Vec2::new(
+                        size.x * (tile.x + side) / 2.0 + (tile.x - side) / 2.0,
+                        size.y * tile.y + tile.y / 2.0,
+                    )
+                } else {
+                    Vec2::new(
+                        size.x * tile.x + tile.x / 2.0,
+                        size.y * (tile.y + side) / 2.0 + (tile.y - side) / 2.0,
+                    )
+                }
+            }
+        }
+    }
+
+    /// Top-left corner of the bounding box of the cell at column `x`, row `y`.
+    pub fn cell_origin(&self, x: i32, y: i32) -> Vec2 {
+        let tile = self.tile_size;
+        let (column, row) = (x as f32, y as f32);
+        match self.orientation {
+            tiled::Orientation::Orthogonal => Vec2::new(column, row) * tile,
+            tiled::Orientation::Isometric => {
+                // Tiled measures from the top corner of the diamond.
+                let top = self.isometric_origin()
+                    + Vec2::new((column - row) * tile.x, (column + row) * tile.y) / 2.0;
+                Vec2::new(top.x - tile.x / 2.0, top.y)
+            }
+            tiled::Orientation::Staggered | tiled::Orientation::Hexagonal => {
+                let side = self.side_length;
+                if self.stagger_x {
+                    let shift = if self.is_shifted(x) {
+                        tile.y / 2.0
+                    } else {
+                        0.0
+                    };
+                    Vec2::new(column * (tile.x + side) / 2.0, row * tile.y + shift)
+                } else {
+                    let shift = if self.is_shifted(y) {
+                        tile.x / 2.0
+                    } else {
+                        0.0
+                    };
+                    Vec2::new(column * tile.x + shift, row * (tile.y + side) / 2.0)
+                }
+            }
+        }
+    }
+
+    /// Fractional cell under a map pixel. Exact for orthogonal and isometric
+    /// maps, ignoring the shifted rows or columns otherwise.
+    pub fn cell_at(&self, pixel: Vec2) -> Vec2 {
+        let tile = self.tile_size;
+        match self.orientation {
+            tiled::Orientation::Orthogonal => pixel / tile,
+            tiled::Orientation::Isometric => {
+                let pixel = pixel - self.isometric_origin();
+                Vec2::new(
+                    pixel.y / tile.y + pixel.x / tile.x,
+                    pixel.y / tile.y - pixel.x / tile.x,
+                )
+            }
+            tiled::Orientation::Staggered | tiled::Orientation::Hexagonal => {
+                let side = self.side_length;
+                if self.stagger_x {
+                    Vec2::new(pixel.x / ((tile.x + side) / 2.0), pixel.y / tile.y)
+                } else {
+                    Vec2::new(pixel.x / tile.x, pixel.y / ((tile.y + side) / 2.0))
+                }
+            }
+        }
+    }
+
+    /// Map pixel of an object placed at `point`. Isometric maps measure object
+    /// coordinates along the grid axes, in tile heights; the others in pixels.
+    pub fn object_to_pixel(&self, point: Vec2) -> Vec2 {
+        match self.orientation {
+            tiled::Orientation::Isometric => {
+                let cell = point / self.tile_size.y;
+                self.isometric_origin()
+                    + Vec2::new(
+                        (cell.x - cell.y) * self.tile_size.x,
+                        (cell.x + cell.y) * self.tile_size.y,
+                    ) / 2.0
+            }
+            _ => point,
+        }
+    }
+
+    /// `shape` with its extents projected like [`TiledProjection::object_to_pixel`]
+    /// projects its origin. On isometric maps rectangles and ellipses become
+    /// the polygons Tiled draws, ellipses approximated by a few segments.
+    pub fn project_shape(&self, shape: &tiled::ObjectShape) -> tiled::ObjectShape {
+        if self.orientation != tiled::Orientation::Isometric {
+            return shape.clone();
+        }
+        let project = |points: &[(f32, f32)]| -> Vec<(f32, f32)> {
+            points
+                .iter()
+                .map(|(x, y)| {
+                    let cell = Vec2::new(*x, *y) / self.tile_size.y;
+                    let pixel = Vec2::new(
+                        (cell.x - cell.y) * self.tile_size.x,
+                        (cell.x + cell.y) * self.tile_size.y,
+                    ) / 2.0;
+                    (pixel.x, pixel.y)
+                })
+                .collect()
+        };
+        match shape {
+            tiled::ObjectShape::Rect { width, height } => tiled::ObjectShape::Polygon {
+                points: project(&[(0.0, 0.0), (*width, 0.0), (*width, *height), (0.0, *height)]),
+            },
+            tiled::ObjectShape::Ellipse { width, height } => {
+                let points: Vec<(f32, f32)> = ellipse_points(Vec2::new(*width, *height))
+                    .into_iter()
+                    .map(|point| (point.x, point.y))
+                    .collect();
+                tiled::ObjectShape::Polygon {
+                    points: project(&points),
+                }
+            }
+            tiled::ObjectShape::Polygon { points } => tiled::ObjectShape::Polygon {
+                points: project(points),
+            },
+            tiled::ObjectShape::Polyline { points } => tiled::ObjectShape::Polyline {
+                points: project(points),
+            },
+            other => other.clone(),
+        }
+    }
+
+    /// Top corner of the diamond of cell (0, 0).
+    fn isometric_origin(&self) -> Vec2 {
+        Vec2::new(self.size.y as f32 * self.tile_size.x / 2.0, 0.0)
+    }
+
+    fn is_shifted(&self, index: i32) -> bool {
+        (index.rem_euclid(2) == 1) == self.stagger_odd
+    }
+}
+
+/// Atlas of a tileset's image, one texture per tile id.
+pub(super) fn tileset_atlas(
+    tileset: &tiled::Tileset,
+    image: &tiled::Image,
+    texture: Handle<Image>,
+) -> TextureAtlas {
+    let mut atlas =
+        TextureAtlas::new_empty(texture, Vec2::new(image.width as f32, image.height as f32));
+    let size = Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32);
+    let columns = tileset.columns.max(1);
+    for id in 0..tileset.tilecount {
+        let cell = Vec2::new((id % columns) as f32, (id / columns) as f32);
+        let min = cell * (size + tileset.spacing as f32) + tileset.margin as f32;
+        atlas.add_texture(Rect {
+            min,
+            max: min + size,
+        });
+    }
+    atlas
+}
+
+/// Spawns the tiles of a region of an isometric, staggered or hexagonal layer
+/// as sprites placed through the map's [`TiledProjection`], under one entity
+/// per tileset. Returns those entities keyed by tileset index.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn spawn_tile_sprites(
+    commands: &mut Commands,
+    tiled_map: &TiledMap,
+    space: &TiledMapSpace,
+    tile_layer: &tiled::TileLayer,
+    context: &TiledLayerContext,
+    z: f32,
+    render: bool,
+    region: TileRegion,
+) -> HashMap<usize, Entity> {
+    if !render {
+        return HashMap::default();
+    }
+
+    let color = context.color();
+
+    let mut sprites: HashMap<usize, Vec<Entity>> = HashMap::default();
+    for y in region.y..region.y + region.height as i32 {
+        for x in region.x..region.x + region.width as i32 {
+            let map_tile = match tile_layer.get_tile(x, y) {
+                Some(v) => v,
+                None => continue,
+            };
+            let tileset_index = map_tile.tileset_index();
+            let atlas = match tiled_map.atlases.get(&tileset_index) {
+                Some(v) => v,
+                None => continue,
+            };
+            let tileset = map_tile.get_tileset();
+            let mut size = Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32);
+
+            let mut sprite = TextureAtlasSprite {
+                index: map_tile.id() as usize,
+                color,
+                flip_x: map_tile.flip_h,
+                flip_y: map_tile.flip_v,
+                ..default()
+            };
+            let mut rotation = Quat::IDENTITY;
+            if map_tile.flip_d {
+                // Flipping along the diagonal is a quarter turn of the image
+                // mirrored horizontally; the other flips swap axes with it.
+                sprite.flip_x = !map_tile.flip_v;
+                sprite.flip_y = map_tile.flip_h;
+                rotation = Quat::from_rotation_z(FRAC_PI_2);
+                size = Vec2::new(size.y, size.x);
+            }
+
+            // Tile images are drawn from the bottom-left corner of their cell.
+            let cell = context.offset + space.projection.cell_origin(x, y);
+            let bottom = cell.y + space.projection.tile_size.y;
+            let center = space.to_local(cell.x + size.x / 2.0, bottom - size.y / 2.0);
+
+            let entity = commands
+                .spawn_bundle(SpriteSheetBundle {
+                    sprite,
+                    texture_atlas: atlas.clone_weak(),
+                    transform: Transform::from_translation(center.extend(bottom * TILE_SORT_STEP))
+                        .with_rotation(rotation),
+                    ..default()
+                })
+                .id();
+
+            insert_tile_components(&mut commands.entity(entity), &map_tile);
+            sprites.entry(tileset_index).or_default().push(entity);
+        }
+    }
+
+    // Every tileset shares the layer's depth, so their tiles sort together.
+    sprites
+        .into_iter()
+        .map(|(tileset_index, children)| {
+            let transform = space.to_world(Vec2::ZERO, z);
+            let parallax = TiledParallax::new(context, transform.translation, space);
+            let mut entity = commands.spawn_bundle(SpatialBundle {
+                transform,
+                visibility: Visibility {
+                    is_visible: context.visible,
+                },
+                ..default()
+            });
+            entity.push_children(&children);
+            if !parallax.is_static() {
+                entity.insert(parallax);
+            }
+            (tileset_index, entity.id())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn projection(
+        orientation: tiled::Orientation,
+        tile_size: Vec2,
+        stagger_x: bool,
+        stagger_odd: bool,
+        side_length: f32,
+    ) -> TiledProjection {
+        TiledProjection {
+            orientation,
+            size: UVec2::new(6, 6),
+            tile_size,
+            stagger_x,
+            stagger_odd,
+            side_length,
+        }
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-4),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn orthogonal_cells() {
+        let p = projection(
+            tiled::Orientation::Orthogonal,
+            Vec2::new(32.0, 32.0),
+            false,
+            false,
+            0.0,
+        );
+        assert_near(p.map_size(), Vec2::new(192.0, 192.0));
+        assert_near(p.cell_origin(0, 0), Vec2::ZERO);
+        assert_near(p.cell_origin(5, 2), Vec2::new(160.0, 64.0));
+        assert_near(p.cell_at(Vec2::new(176.0, 80.0)), Vec2::new(5.5, 2.5));
+        assert_near(
+            p.object_to_pixel(Vec2::new(16.0, 40.0)),
+            Vec2::new(16.0, 40.0),
+        );
+    }
+
+    #[test]
+    fn isometric_cells() {
+        let p = projection(
+            tiled::Orientation::Isometric,
+            Vec2::new(64.0, 32.0),
+            false,
+            false,
+            0.0,
+        );
+        assert_near(p.map_size(), Vec2::new(384.0, 192.0));
+        // Cell (0, 0) is the top diamond, row 0 runs down to the right.
+        assert_near(p.cell_origin(0, 0), Vec2::new(160.0, 0.0));
+        assert_near(p.cell_origin(1, 0), Vec2::new(192.0, 16.0));
+        assert_near(p.cell_origin(0, 1), Vec2::new(128.0, 16.0));
+        assert_near(p.cell_origin(5, 5), Vec2::new(160.0, 160.0));
+
+        assert_near(p.cell_at(Vec2::new(192.0, 0.0)), Vec2::ZERO);
+        assert_near(p.cell_at(Vec2::new(192.0, 176.0)), Vec2::new(5.5, 5.5));
+        assert_near(p.cell_at(Vec2::new(352.0, 96.0)), Vec2::new(5.5, 0.5));
+
+        // Objects count tile heights along the grid axes.
+        assert_near(p.object_to_pixel(Vec2::ZERO), Vec2::new(192.0, 0.0));
+        assert_near(
+            p.object_to_pixel(Vec2::new(32.0, 0.0)),
+            Vec2::new(224.0, 16.0),
+        );
+        assert_near(
+            p.object_to_pixel(Vec2::new(0.0, 32.0)),
+            Vec2::new(160.0, 16.0),
+        );
+    }
+
+    #[test]
+    fn staggered_cells() {
+        let tile = Vec2::new(64.0, 32.0);
+
+        // Columns staggered, even ones shifted down by half a tile.
+        let p = projection(tiled::Orientation::Staggered, tile, true, false, 0.0);
+        assert_near(p.map_size(), Vec2::new(224.0, 208.0));
+        assert_near(p.cell_origin(0, 0), Vec2::new(0.0, 16.0));
+        assert_near(p.cell_origin(1, 0), Vec2::new(32.0, 0.0));
+        assert_near(p.cell_origin(5, 5), Vec2::new(160.0, 160.0));
+        assert_near(p.cell_at(Vec2::new(160.0, 160.0)), Vec2::new(5.0, 5.0));
+
+        // Odd columns shifted instead.
+        let p = projection(tiled::Orientation::Staggered, tile, true, true, 0.0);
+        assert_near(p.cell_origin(0, 0), Vec2::ZERO);
+        assert_near(p.cell_origin(1, 0), Vec2::new(32.0, 16.0));
+
+        // Rows staggered, even ones shifted right by half a tile.
+        let p = projection(tiled::Orientation::Staggered, tile, false, false, 0.0);
+        assert_near(p.map_size(), Vec2::new(416.0, 112.0));
+        assert_near(p.cell_origin(0, 0), Vec2::new(32.0, 0.0));
+        assert_near(p.cell_origin(0, 1), Vec2::new(0.0, 16.0));
+        assert_near(p.cell_at(Vec2::new(64.0, 16.0)), Vec2::new(1.0, 1.0));
+
+        // Objects are placed in plain pixels.
+        assert_near(
+            p.object_to_pixel(Vec2::new(10.0, 20.0)),
+            Vec2::new(10.0, 20.0),
+        );
+    }
+
+    #[test]
+    fn hexagonal_cells() {
+        // Rows staggered, odd ones shifted right.
+        let p = projection(
+            tiled::Orientation::Hexagonal,
+            Vec2::new(56.0, 64.0),
+            false,
+            true,
+            32.0,
+        );
+        assert_near(p.map_size(), Vec2::new(364.0, 304.0));
+        assert_near(p.cell_origin(0, 0), Vec2::ZERO);
+        assert_near(p.cell_origin(0, 1), Vec2::new(28.0, 48.0));
+        assert_near(p.cell_origin(5, 5), Vec2::new(308.0, 240.0));
+        assert_near(p.cell_at(Vec2::new(224.0, 192.0)), Vec2::new(4.0, 4.0));
+
+        // Columns staggered, even ones shifted down.
+        let p = projection(
+            tiled::Orientation::Hexagonal,
+            Vec2::new(64.0, 56.0),
+            true,
+            false,
+            32.0,
+        );
+        assert_near(p.cell_origin(0, 0), Vec2::new(0.0, 28.0));
+        assert_near(p.cell_origin(1, 0), Vec2::new(48.0, 0.0));
+        assert_near(p.cell_at(Vec2::new(96.0, 56.0)), Vec2::new(2.0, 1.0));
+    }
+
+    #[test]
+    fn isometric_shapes_are_projected() {
+        let p = projection(
+            tiled::Orientation::Isometric,
+            Vec2::new(64.0, 32.0),
+            false,
+            false,
+            0.0,
+        );
+        let points = match p.project_shape(&tiled::ObjectShape::Rect {
+            width: 32.0,
+            height: 64.0,
+        }) {
+            tiled::ObjectShape::Polygon { points } => points,
+            other => panic!("expected a polygon, got {:?}", other),
+        };
+        assert_eq!(
+            points,
+            vec![(0.0, 0.0), (32.0, 16.0), (-32.0, 48.0), (-64.0, 32.0)]
+        );
+
+        // A circle filling one cell touches the middle of each side of its diamond.
+        let points = match p.project_shape(&tiled::ObjectShape::Ellipse {
+            width: 32.0,
+            height: 32.0,
+        }) {
+            tiled::ObjectShape::Polygon { points } => points,
+            other => panic!("expected a polygon, got {:?}", other),
+        };
+        assert_eq!(points.len(), 16);
+        for (i, expected) in [
+            (0, (16.0, 24.0)),
+            (4, (-16.0, 24.0)),
+            (8, (-16.0, 8.0)),
+            (12, (16.0, 8.0)),
+        ] {
+            assert_near(
+                Vec2::new(points[i].0, points[i].1),
+                Vec2::new(expected.0, expected.1),
+            );
+        }
+
+        let p = projection(
+            tiled::Orientation::Orthogonal,
+            Vec2::new(32.0, 32.0),
+            false,
+            false,
+            0.0,
+        );
+        assert!(matches!(
+            p.project_shape(&tiled::ObjectShape::Ellipse {
+                width: 32.0,
+                height: 16.0
+            }),
+            tiled::ObjectShape::Ellipse { .. }
+        ));
+    }
+
+    /// The markers of the orientation test maps sit on the centres of the
+    /// first and last cells, which their tiles are drawn in.
+    #[test]
+    fn orientation_maps_place_markers_on_cells() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/tiled/orientations");
+        for name in ["orthogonal", "isometric", "staggered", "hexagonal"] {
+            let map = tiled::Loader::new()
+                .load_tmx_map(dir.join(format!("{}.tmx", name)))
+                .unwrap();
+            let p = TiledProjection::new(&map);
+
+            let markers = map
+                .layers()
+                .find_map(|layer| match layer.layer_type() {
+                    tiled::LayerType::Objects(v) => Some(v),
+                    _ => None,
+                })
+                .unwrap();
+            for (object, cell) in markers.objects().zip([(0, 0), (5, 5)]) {
+                let expected = p.cell_origin(cell.0, cell.1) + p.tile_size / 2.0;
+                let actual = p.object_to_pixel(Vec2::new(object.x, object.y));
+                assert!(
+                    actual.abs_diff_eq(expected, 1e-4),
+                    "{}: {} at {}, expected {}",
+                    name,
+                    object.name,
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+}
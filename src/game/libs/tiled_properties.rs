@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Custom properties set in Tiled. The map entity always carries its map's,
+/// layer, tile and object entities only when they have some.
+///
+/// Only tile and image layers get an entity: the properties of object layers
+/// and group layers are not spawned anywhere, nor merged into their objects
+/// or nested layers. Set such properties on the objects or layers themselves.
+///
+/// Single values are read with the typed getters, or the whole set is
+/// deserialized into a struct deriving `serde::Deserialize`:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct Level {
+///     music: String,
+///     #[serde(default)]
+///     dark: bool,
+/// }
+///
+/// let level: Level = properties.deserialize()?;
+/// ```
+#[derive(Component, Debug, Clone, Default)]
+pub struct TiledProperties(pub tiled::Properties);
+
+impl TiledProperties {
+    /// The properties, unless there are none.
+    pub fn non_empty(properties: &tiled::Properties) -> Option<Self> {
+        (!properties.is_empty()).then(|| Self(properties.clone()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&tiled::PropertyValue> {
+        self.0.get(name)
+    }
+
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            tiled::PropertyValue::BoolValue(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Option<i32> {
+        match self.get(name)? {
+            tiled::PropertyValue::IntValue(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, name: &str) -> Option<f32> {
+        match self.get(name)? {
+            tiled::PropertyValue::FloatValue(v) => Some(*v),
+            tiled::PropertyValue::IntValue(v) => Some(*v as f32),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            tiled::PropertyValue::StringValue(v) | tiled::PropertyValue::FileValue(v) => {
+                Some(v.as_str())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn color(&self, name: &str) -> Option<Color> {
+        match self.get(name)? {
+            tiled::PropertyValue::ColorValue(v) => {
+                Some(Color::rgba_u8(v.red, v.green, v.blue, v.alpha))
+            }
+            _ => None,
+        }
+    }
+
+    /// Deserializes the properties into `T`, see [`properties_to_json`].
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_value(properties_to_json(&self.0))
+    }
+}
+
+/// The properties as a JSON object: colors become `#aarrggbb` strings like in
+/// Tiled, object references their id, and class values nested objects.
+pub fn properties_to_json(properties: &tiled::Properties) -> Value {
+    let object: Map<String, Value> = properties
+        .iter()
+        .map(|(name, value)| (name.clone(), property_to_json(value)))
+        .collect();
+    Value::Object(object)
+}
+
+fn property_to_json(value: &tiled::PropertyValue) -> Value {
+    match value {
+        tiled::PropertyValue::BoolValue(v) => Value::from(*v),
+        tiled::PropertyValue::IntValue(v) => Value::from(*v),
+        tiled::PropertyValue::FloatValue(v) => Value::from(*v as f64),
+        tiled::PropertyValue::StringValue(v) | tiled::PropertyValue::FileValue(v) => {
+            Value::from(v.as_str())
+        }
+        tiled::PropertyValue::ColorValue(v) => Value::from(format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            v.alpha, v.red, v.green, v.blue
+        )),
+        tiled::PropertyValue::ObjectValue(v) => Value::from(*v),
+        tiled::PropertyValue::ClassValue { properties, .. } => properties_to_json(properties),
+    }
+}
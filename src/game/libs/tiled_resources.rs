@@ -0,0 +1,267 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+
+use super::tiled_map::TiledMap;
+
+/// External tilesets (`.tsx`) and object templates (`.tx`) parsed by the map
+/// loader, shared between every map that references them.
+#[derive(Clone, Default)]
+pub struct TiledResourceCache(Arc<RwLock<CachedResources>>);
+
+#[derive(Default)]
+struct CachedResources {
+    tilesets: HashMap<PathBuf, Arc<tiled::Tileset>>,
+    templates: HashMap<PathBuf, Arc<tiled::Template>>,
+}
+
+impl TiledResourceCache {
+    /// Forgets a file so that the next map using it parses it again.
+    pub fn evict(&self, path: &Path) {
+        let mut cached = self.0.write().unwrap();
+        cached.tilesets.remove(path);
+        cached.templates.remove(path);
+    }
+
+    /// Forgets the files `keep` rejects.
+    pub fn retain(&self, keep: impl Fn(&Path) -> bool) {
+        let mut cached = self.0.write().unwrap();
+        cached.tilesets.retain(|path, _| keep(path));
+        cached.templates.retain(|path, _| keep(path));
+    }
+}
+
+/// Resolves the `.` and `..` components tiled leaves in the paths it joins,
+/// such as `tiled/../tilesets/a.tsx`, so that they match asset paths.
+pub(super) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// A tileset or template file. Maps keep it as a dependency, so that the
+/// asset server watches it and [`reload_dependent_maps`] can react to changes.
+#[derive(TypeUuid)]
+#[uuid = "2cd6a40e-415e-4266-b32a-e9a604e483a4"]
+pub struct TiledDependency;
+
+#[derive(Default)]
+pub struct TiledDependencyLoader;
+
+impl AssetLoader for TiledDependencyLoader {
+    fn load<'a>(
+        &'a self,
+        _bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            load_context.set_default_asset(LoadedAsset::new(TiledDependency));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tsx", "tx"]
+    }
+}
+
+/// Parses the map at `load_context.path()` from `tmx`, reading the external
+/// files it references through `load_context` so they come from the asset
+/// source (and its embedded assets) rather than straight from disk.
+///
+/// Returns the map and the paths of every tileset and template it uses.
+pub(super) async fn load_tmx_map(
+    load_context: &LoadContext<'_>,
+    cache: &TiledResourceCache,
+    tmx: &[u8],
+) -> anyhow::Result<(tiled::Map, Vec<PathBuf>)> {
+    let path = normalize_path(load_context.path());
+    let mut files = HashMap::default();
+    files.insert(path.clone(), tmx.to_vec());
+    let used = Arc::new(Mutex::new(Vec::new()));
+
+    // The tiled parser is synchronous: each time it asks for a file we don't
+    // have yet, fetch it and parse again.
+    loop {
+        let result = tiled::Loader::with_cache_and_reader(
+            SharedCache {
+                cache: cache.clone(),
+                used: used.clone(),
+            },
+            PrefetchedReader { files: &files },
+        )
+        .load_tmx_map(&path);
+
+        match result {
+            Ok(map) => {
+                let mut used = used.lock().unwrap().clone();
+                used.sort();
+                used.dedup();
+                return Ok((map, used));
+            }
+            Err(tiled::Error::ResourceLoadingError { path: missing, .. })
+                if !files.contains_key(&normalize_path(&missing)) =>
+            {
+                let missing = normalize_path(&missing);
+                let bytes = load_context.read_asset_bytes(&missing).await?;
+                files.insert(missing, bytes);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Reloads the maps using a tileset or template that changed on disk.
+pub(super) fn reload_dependent_maps(
+    mut events: EventReader<AssetEvent<TiledDependency>>,
+    asset_server: Res<AssetServer>,
+    cache: Res<TiledResourceCache>,
+    maps: Res<Assets<TiledMap>>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Modified { handle } => handle,
+            _ => continue,
+        };
+        let path = match asset_server.get_handle_path(handle) {
+            Some(v) => v.path().to_path_buf(),
+            None => continue,
+        };
+        info!("tiled dependency changed: {:?}", path);
+        cache.evict(&path);
+
+        for (map_id, map) in maps.iter() {
+            if !map.dependencies.contains(&path) {
+                continue;
+            }
+            if let Some(map_path) = asset_server.get_handle_path(map_id) {
+                asset_server.reload_asset(map_path.path());
+            }
+        }
+    }
+}
+
+/// Drops the cached files no loaded map uses anymore once maps are unloaded.
+pub(super) fn prune_resource_cache(
+    mut events: EventReader<AssetEvent<TiledMap>>,
+    cache: Res<TiledResourceCache>,
+    maps: Res<Assets<TiledMap>>,
+) {
+    let removed = events
+        .iter()
+        .filter(|it| matches!(it, AssetEvent::Removed { .. }))
+        .count();
+    if removed == 0 {
+        return;
+    }
+    cache.retain(|path| {
+        maps.iter()
+            .any(|(_, map)| map.dependencies.iter().any(|it| it == path))
+    });
+}
+
+/// Serves the files fetched so far, reporting the others as missing.
+struct PrefetchedReader<'a> {
+    files: &'a HashMap<PathBuf, Vec<u8>>,
+}
+
+impl<'a> tiled::ResourceReader for PrefetchedReader<'a> {
+    type Resource = &'a [u8];
+    type Error = io::Error;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        self.files
+            .get(&normalize_path(path))
+            .map(Vec::as_slice)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{:?} not fetched yet", path),
+                )
+            })
+    }
+}
+
+/// The shared cache, remembering which files one map asked for.
+struct SharedCache {
+    cache: TiledResourceCache,
+    used: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl tiled::ResourceCache for SharedCache {
+    fn get_tileset(&self, path: impl AsRef<tiled::ResourcePath>) -> Option<Arc<tiled::Tileset>> {
+        let path = normalize_path(path.as_ref());
+        self.used.lock().unwrap().push(path.clone());
+        self.cache.0.read().unwrap().tilesets.get(&path).cloned()
+    }
+
+    fn insert_tileset(
+        &mut self,
+        path: impl AsRef<tiled::ResourcePath>,
+        tileset: Arc<tiled::Tileset>,
+    ) {
+        let path = normalize_path(path.as_ref());
+        self.cache.0.write().unwrap().tilesets.insert(path, tileset);
+    }
+
+    fn get_template(&self, path: impl AsRef<tiled::ResourcePath>) -> Option<Arc<tiled::Template>> {
+        let path = normalize_path(path.as_ref());
+        self.used.lock().unwrap().push(path.clone());
+        self.cache.0.read().unwrap().templates.get(&path).cloned()
+    }
+
+    fn insert_template(
+        &mut self,
+        path: impl AsRef<tiled::ResourcePath>,
+        template: Arc<tiled::Template>,
+    ) {
+        let path = normalize_path(path.as_ref());
+        self.cache
+            .0
+            .write()
+            .unwrap()
+            .templates
+            .insert(path, template);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_path_resolves_dots() {
+        assert_eq!(
+            normalize_path(Path::new("tiled/../tilesets/a.tsx")),
+            PathBuf::from("tilesets/a.tsx")
+        );
+        assert_eq!(
+            normalize_path(Path::new("tiled/./maps/../01.tmx")),
+            PathBuf::from("tiled/01.tmx")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../../shared/b.tx")),
+            PathBuf::from("../../shared/b.tx")
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 
-use super::{GameState, libs::{AnimationData, AnimationLoader, Animation}};
+use super::{GameState, libs::{AnimationData, AnimationGraph, AnimationLoader, Animation, HitboxSet}};
 
 pub struct LoadingPlugin;
 
@@ -13,7 +13,6 @@ impl Plugin for LoadingPlugin {
             LoadingState::new(GameState::Loading)
             .continue_to_state(GameState::Start)
             .with_collection::<AnimationAssets>()
-            .with_collection::<TextureAssets>()
             .init_resource::<Animation>()
         );
     }
@@ -24,12 +23,9 @@ impl Plugin for LoadingPlugin {
 pub struct AnimationAssets {
     #[asset(path = "animation/player01.anim_ske.json")]
     pub player01: Handle<AnimationData>,
-}
-
-
-#[derive(AssetCollection)]
-pub struct TextureAssets {
-    #[asset(path = "tiled/textures/780.jpg")]
-    pub bg: Handle<Image>,
+    #[asset(path = "animation/player01.anim_graph.ron")]
+    pub player01_graph: Handle<AnimationGraph>,
+    #[asset(path = "animation/player01.hitboxes.ron")]
+    pub player01_hitboxes: Handle<HitboxSet>,
 }
 
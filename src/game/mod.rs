@@ -1,11 +1,15 @@
 mod libs;
 
+mod input;
+mod level;
 mod loading;
 mod playing;
 
 use bevy::prelude::*;
 
-use self::{loading::LoadingPlugin, playing::PlayingPlugin};
+use self::{
+    input::ActionsPlugin, level::LevelPlugin, loading::LoadingPlugin, playing::PlayingPlugin,
+};
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
@@ -21,7 +25,9 @@ pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state(GameState::Loading)
+            .add_plugin(ActionsPlugin)
             .add_plugin(LoadingPlugin)
-            .add_plugin(PlayingPlugin);
+            .add_plugin(PlayingPlugin)
+            .add_plugin(LevelPlugin);
     }
 }
@@ -1,16 +1,19 @@
-use std::{collections::HashMap, time::Duration};
-
 use super::{
-    libs::{AnimationData, TiledMapBundle, TiledMapPlugin},
-    loading::{AnimationAssets, TextureAssets},
+    input::{Action, ActionState, InputBindings, Rebinding},
+    level::{LevelEntered, LevelManager},
+    libs::{
+        move_characters, run_state_machines, AnimationData, AnimationGraph, AnimationGraphPlugin,
+        AnimationStateMachine, CharacterControllerBundle, CharacterControllerPlugin,
+        CharacterInput, CharacterState, DragonAnimationPlayer, DragonAnimationPlugin,
+        DragonHitboxPlugin, DragonHitboxes, DropThrough, NavigationDebug, NavigationPlugin,
+        OneWayPlatformPlugin, OneWayPlatformQuery, TileAnimationClock, TiledMapPlugin,
+    },
+    loading::AnimationAssets,
     GameState,
 };
 
 use bevy::{prelude::*, render::camera::ScalingMode};
-use bevy_rapier2d::prelude::{
-    CoefficientCombineRule, Collider, Friction, GravityScale, LockedAxes, NoUserData, QueryFilter,
-    RapierContext, RapierPhysicsPlugin, RigidBody, Velocity,
-};
+use bevy_rapier2d::prelude::{Collider, RapierConfiguration, RapierPhysicsPlugin};
 use smooth_bevy_cameras::{LookTransform, LookTransformBundle, LookTransformPlugin, Smoother};
 
 pub struct PlayingPlugin;
@@ -18,16 +21,33 @@ pub struct PlayingPlugin;
 impl Plugin for PlayingPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(TiledMapPlugin);
+        app.add_plugin(DragonAnimationPlugin);
+        app.add_plugin(AnimationGraphPlugin);
+        app.add_plugin(DragonHitboxPlugin);
         app.add_plugin(LookTransformPlugin);
         // app.add_plugin(bevy_inspector_egui::WorldInspectorPlugin::default());
         // app.add_plugin(bevy_rapier2d::prelude::RapierDebugRenderPlugin::default());
-        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0));
+        app.add_plugin(RapierPhysicsPlugin::<OneWayPlatformQuery>::pixels_per_meter(1.0));
+        app.add_plugin(OneWayPlatformPlugin);
+        app.add_plugin(CharacterControllerPlugin);
+        app.add_plugin(NavigationPlugin);
         app.add_event::<MoveCameraEvent>()
+            .init_resource::<Pause>()
             .add_system_set(SystemSet::on_enter(GameState::Start).with_system(spawn_scene))
             .add_system_set(
                 SystemSet::on_update(GameState::Start)
-                    .with_system(animate_sprite)
-                    .with_system(move_camera),
+                    .with_system(enter_level)
+                    .with_system(toggle_pause.before(control_player))
+                    .with_system(control_player.before(move_characters))
+                    .with_system(
+                        animate_sprite
+                            .after(move_characters)
+                            .before(run_state_machines),
+                    )
+                    .with_system(move_camera)
+                    .with_system(toggle_navigation_debug)
+                    .with_system(start_rebinding)
+                    .with_system(update_help_text.after(start_rebinding)),
             );
     }
 
@@ -36,156 +56,23 @@ impl Plugin for PlayingPlugin {
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
-enum Status {
-    Idle,
-    Walk,
-    Run,
-    Jump1,
-    Jump2,
-    Jump3,
-    Jump4,
-    Jump5,
-    // Attack,
-}
-
-impl Status {
-    fn to_str(&self) -> &str {
-        match self {
-            Status::Idle => "idle",
-            Status::Walk => "walk",
-            Status::Run => "run",
-            Status::Jump1 => "jump_1",
-            Status::Jump2 => "jump_2",
-            Status::Jump3 => "jump_3",
-            Status::Jump4 => "jump_4",
-            Status::Jump5 => "jump_5",
-            // Status::Attack => "attack",
-        }
-    }
-}
-
-#[derive(Clone)]
-struct Args {
-    speed: f32,
-    velocity_y: f32,
-    is_ground: bool,
-    jump: bool,
-    last_frame: bool,
-}
-
 #[derive(Component)]
-struct StateMachine {
-    state: Status,
-    args: Args,
-    index: i32,
-}
-
-impl StateMachine {
-    fn run(&mut self) -> bool {
-        let a = match self.state {
-            Status::Idle => {
-                if self.args.speed.abs() > 0.1 && self.args.speed.abs() < 0.5 {
-                    Status::Walk
-                } else if self.args.speed.abs() > 0.5 {
-                    Status::Run
-                } else if self.args.jump {
-                    self.args.jump = false;
-                    Status::Jump1
-                } else if !self.args.is_ground {
-                    Status::Jump4
-                } else {
-                    self.state
-                }
-            }
-            Status::Walk => {
-                if self.args.speed.abs() < 0.1 {
-                    Status::Idle
-                } else if self.args.speed.abs() > 0.5 {
-                    Status::Run
-                } else if self.args.jump {
-                    self.args.jump = false;
-                    Status::Jump1
-                } else if !self.args.is_ground {
-                    Status::Jump4
-                } else {
-                    self.state
-                }
-            }
-            Status::Run => {
-                if self.args.speed.abs() < 0.1 {
-                    Status::Idle
-                } else if self.args.speed.abs() < 0.5 {
-                    Status::Walk
-                } else if self.args.jump {
-                    self.args.jump = false;
-                    Status::Jump1
-                } else if !self.args.is_ground {
-                    Status::Jump4
-                } else {
-                    self.state
-                }
-            }
-            Status::Jump1 => {
-                if self.args.last_frame {
-                    Status::Jump2
-                } else {
-                    self.state
-                }
-            }
-            Status::Jump2 => {
-                if self.args.velocity_y < 0.0 {
-                    Status::Jump3
-                } else {
-                    self.state
-                }
-            }
-            Status::Jump3 => {
-                if self.args.last_frame {
-                    Status::Jump4
-                } else {
-                    self.state
-                }
-            }
-            Status::Jump4 => {
-                if self.args.is_ground {
-                    Status::Jump5
-                } else {
-                    self.state
-                }
-            }
-            Status::Jump5 => {
-                if self.args.last_frame {
-                    Status::Idle
-                } else {
-                    self.state
-                }
-            } // _ => self.state,
-        };
-
-        self.args.last_frame = false;
+pub(super) struct Player;
 
-        if self.state != a {
-            self.state = a;
-            self.index = 0;
-            return true;
-        }
-        false
-    }
+/// Whether the game is paused: physics, characters and animations stop,
+/// and the player's actions are ignored until `Action::Pause` again.
+#[derive(Default)]
+pub(super) struct Pause {
+    pub paused: bool,
 }
 
 fn spawn_scene(
     mut commands: Commands,
-    ass: Res<AnimationAssets>,
-    anim: Res<Assets<AnimationData>>,
     asset_server: Res<AssetServer>,
-    texture_assets: Res<TextureAssets>,
+    mut levels: ResMut<LevelManager>,
+    bindings: Res<InputBindings>,
 ) {
-    let pos = Vec3 {
-        x: -50.0,
-        y: 0.0,
-        z: 0.0,
-    };
+    let pos = Vec3::ZERO;
 
     commands
         .spawn_bundle(Camera2dBundle {
@@ -203,41 +90,108 @@ fn spawn_scene(
             smoother: Smoother::new(0.9),
         });
 
-    commands.spawn_bundle(SpriteBundle {
-        transform: Transform {
-            scale: Vec3::new(3.0, 3.0, 1.0),
-            translation: Vec3::new(0.0, -100.0, -1.0),
+    levels.load("01", "start");
+
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::from_section(
+                help_text(&bindings, None),
+                TextStyle {
+                    font_size: 24.0,
+                    font: asset_server.load("fonts/MSYH.TTF"),
+                    ..default()
+                },
+            ),
+            style: Style { ..default() },
             ..default()
-        },
-        texture: texture_assets.bg.clone(),
-        ..default()
-    });
+        })
+        .insert(HelpText);
+}
 
-    commands.spawn_bundle(TiledMapBundle {
-        tiled_map: asset_server.load("tiled/01.tmx"),
-        ..default()
-    });
+/// Actions explained on screen, with what they do.
+const HELP: [(Action, &str); 7] = [
+    (Action::MoveX, "移动"),
+    (Action::Run, "按住奔跑"),
+    (Action::Jump, "跳跃, 按住跳的更高, 贴墙时蹬墙跳"),
+    (Action::Attack, "攻击, 连按连击"),
+    (Action::Dash, "冲刺"),
+    (Action::MoveY, "上: 进门, 爬上边缘, 下: 穿过单向平台"),
+    (Action::Pause, "暂停"),
+];
+
+/// Keys rebinding the actions of [`HELP`], in the same order.
+const REBIND_KEYS: [KeyCode; 7] = [
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+];
 
-    let n = anim.get(&ass.player01).unwrap();
+#[derive(Component)]
+struct HelpText;
+
+fn help_text(bindings: &InputBindings, pending: Option<Action>) -> String {
+    if let Some((_, description)) = HELP.iter().find(|(action, _)| Some(*action) == pending) {
+        return format!(
+            "为 \"{}\" 按下新的按键或摇杆\n方向轴依次按下负方向和正方向的按键",
+            description
+        );
+    }
+    format!(
+        "{}F3 显示寻路网格\nF5-F11 依次重新绑定以上操作",
+        bindings.help_text(&HELP)
+    )
+}
+
+/// Keeps the help in line with the bindings when they change, and shows
+/// which action is being rebound.
+fn update_help_text(
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut query: Query<&mut Text, With<HelpText>>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for mut text in &mut query {
+        text.sections[0].value = help_text(&bindings, rebinding.pending());
+    }
+}
+
+/// Waits for a new binding of an action when its key in [`REBIND_KEYS`] is pressed.
+fn start_rebinding(keyboard_input: Res<Input<KeyCode>>, mut rebinding: ResMut<Rebinding>) {
+    if rebinding.pending().is_some() {
+        return;
+    }
+    for (key, (action, _)) in REBIND_KEYS.iter().zip(HELP) {
+        if keyboard_input.just_pressed(*key) {
+            rebinding.start(action);
+        }
+    }
+}
+
+/// Puts the player at the spawn point of each level entered, spawning it the first time.
+fn enter_level(
+    mut commands: Commands,
+    ass: Res<AnimationAssets>,
+    anim: Res<Assets<AnimationData>>,
+    mut events: EventReader<LevelEntered>,
+    mut players: Query<(&mut Transform, &mut CharacterState), With<Player>>,
+) {
+    let pos = match events.iter().last() {
+        Some(event) => event.position,
+        None => return,
+    };
+    if let Ok((mut transform, mut state)) = players.get_single_mut() {
+        transform.translation = pos;
+        state.reset();
+        return;
+    }
 
-    // commands
-    //     .spawn()
-    //     .insert(Collider::cuboid(640.0, 10.0))
-    //     .insert_bundle(TransformBundle::from(Transform::from_xyz(0.0, -230.0, 0.0)));
-
-    // commands
-    //     .spawn()
-    //     .insert(Collider::cuboid(10.0, 230.0))
-    //     .insert_bundle(TransformBundle::from(Transform::from_xyz(
-    //         -460.0, -80.0, 0.0,
-    //     )));
-
-    // commands
-    //     .spawn()
-    //     .insert(Collider::cuboid(10.0, 230.0))
-    //     .insert_bundle(TransformBundle::from(Transform::from_xyz(
-    //         460.0, -80.0, 0.0,
-    //     )));
+    let n = anim.get(&ass.player01).unwrap();
 
     commands
         .spawn_bundle(SpriteSheetBundle {
@@ -249,45 +203,13 @@ fn spawn_scene(
             transform: Transform::from_translation(pos),
             ..default()
         })
-        .insert(AnimationTimer(Timer::from_seconds(1.0, true)))
-        .insert(StateMachine {
-            state: Status::Idle,
-            args: Args {
-                speed: 0.0,
-                velocity_y: 0.0,
-                is_ground: true,
-                jump: false,
-                last_frame: false,
-            },
-            index: 0,
-        })
-        .insert(RigidBody::Dynamic)
-        .insert(GravityScale(7.0))
+        .insert(Player)
+        .insert(DragonAnimationPlayer::new(ass.player01.clone(), "idle"))
+        .insert(AnimationStateMachine::new(ass.player01_graph.clone()))
+        .insert(DragonHitboxes::new(Some(ass.player01_hitboxes.clone())))
+        .insert_bundle(CharacterControllerBundle::default())
         .insert(Collider::capsule_y(17.0, 15.0))
-        .insert(Velocity::default())
-        .insert(LockedAxes::ROTATION_LOCKED)
-        .insert(Friction {
-            coefficient: 0.0,
-            combine_rule: CoefficientCombineRule::Min,
-        });
-
-    commands.spawn_bundle(TextBundle {
-        text: Text::from_section(
-            r#"
-                左右控制移动
-                按住 Z + 方向键 奔跑
-                空格键 跳跃
-                按住 空格键 跳的更高
-            "#,
-            TextStyle {
-                font_size: 24.0,
-                font: asset_server.load("fonts/MSYH.TTF"),
-                ..default()
-            },
-        ),
-        style: Style { ..default() },
-        ..default()
-    });
+        .insert(DropThrough::default());
 }
 
 struct MoveCameraEvent {
@@ -303,144 +225,125 @@ fn move_camera(mut events: EventReader<MoveCameraEvent>, mut query: Query<(&mut
     }
 }
 
-#[derive(Component, Deref, DerefMut)]
-struct AnimationTimer(Timer);
-
-fn animate_sprite(
-    time: Res<Time>,
-    animation: Res<AnimationAssets>,
-    animation_assets: Res<Assets<AnimationData>>,
+fn toggle_navigation_debug(
     keyboard_input: Res<Input<KeyCode>>,
-    rapier_context: Res<RapierContext>,
-    mut move_events: EventWriter<MoveCameraEvent>,
-    mut query: Query<(
-        &mut AnimationTimer,
-        &mut TextureAtlasSprite,
-        &mut StateMachine,
-        &mut Velocity,
-        &mut GravityScale,
-        &Transform,
-    )>,
+    mut debug: ResMut<NavigationDebug>,
 ) {
-    let anim = animation_assets.get(&animation.player01).unwrap();
-    let mut animation_map = HashMap::new();
-
-    for armature in anim.ske.armature.iter() {
-        let skin = armature.skin.first().unwrap();
-        for animation_fragment in armature.animation.iter() {
-            animation_map.insert(
-                animation_fragment.name.clone(),
-                (
-                    animation_fragment,
-                    skin.slot.first().unwrap().display.clone(),
-                    armature.frame_rate,
-                ),
-            );
-        }
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        debug.enabled = !debug.enabled;
     }
+}
 
-    for (mut timer, mut sprite, mut player, mut velocity, mut gravity, transform) in &mut query {
-        timer.tick(time.delta());
-
-        let mut is_timer_done = timer.just_finished();
-
-        if let Some(_) = rapier_context.cast_ray(
-            transform.translation.truncate(),
-            Vec2 { x: 0.0, y: -1.0 },
-            32.0 + 5.0,
-            false,
-            QueryFilter::default().exclude_dynamic(),
-        ) {
-            if !player.args.is_ground {
-                player.args.is_ground = true;
-                gravity.0 = 7.0;
-            }
-        } else {
-            player.args.is_ground = false;
-        }
+fn toggle_pause(
+    actions: Res<ActionState>,
+    mut pause: ResMut<Pause>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut clock: ResMut<TileAnimationClock>,
+    mut players: Query<&mut DragonAnimationPlayer>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    pause.paused = !pause.paused;
+    // Also stops the character controller.
+    rapier_config.physics_pipeline_active = !pause.paused;
+    clock.paused = pause.paused;
+    for mut player in &mut players {
+        player.paused = pause.paused;
+    }
+}
 
-        if keyboard_input.pressed(KeyCode::Left) {
-            sprite.flip_x = true;
-            player.args.speed = if keyboard_input.pressed(KeyCode::Z) {
-                -0.6
-            } else {
-                -0.4
-            };
-        } else if keyboard_input.pressed(KeyCode::Right) {
-            sprite.flip_x = false;
-            player.args.speed = if keyboard_input.pressed(KeyCode::Z) {
-                0.6
-            } else {
-                0.4
-            };
+/// Turns the player's actions into what its character controller should do.
+fn control_player(
+    actions: Res<ActionState>,
+    pause: Res<Pause>,
+    graphs: Res<Assets<AnimationGraph>>,
+    mut query: Query<
+        (
+            &mut CharacterInput,
+            &CharacterState,
+            &mut DropThrough,
+            &mut AnimationStateMachine,
+        ),
+        With<Player>,
+    >,
+) {
+    if pause.paused {
+        return;
+    }
+    for (mut input, state, mut drop, mut machine) in &mut query {
+        // Ground attacks root the player until their clip ends.
+        let attacking = state.grounded
+            && machine
+                .state()
+                .map_or(false, |it| it.starts_with("attack_"));
+
+        input.move_x = if attacking {
+            0.0
         } else {
-            player.args.speed = 0.0;
+            actions.value(Action::MoveX)
         };
-
-        player.args.velocity_y = velocity.linvel.y;
-
-        if keyboard_input.just_pressed(KeyCode::Space) && player.args.is_ground {
-            velocity.linvel.y = 600.0;
-            player.args.jump = true;
-        }
-
-        if keyboard_input.just_released(KeyCode::Space) && !player.args.is_ground {
-            gravity.0 = 15.0;
+        input.move_y = actions.value(Action::MoveY);
+        input.run = actions.pressed(Action::Run) && !attacking;
+        input.jump = actions.just_pressed(Action::Jump);
+        input.jump_held = actions.pressed(Action::Jump);
+        input.dash = actions.just_pressed(Action::Dash);
+
+        // The graph picks the ground combo step or the air attack. Presses
+        // no state takes, e.g. while landing, are dropped rather than kept.
+        let listens = graphs
+            .get(&machine.graph)
+            .map_or(false, |graph| machine.listens_to(graph, "attack"));
+        if actions.just_pressed(Action::Attack)
+            && listens
+            && !(state.hanging || state.wall_sliding || state.dashing)
+        {
+            machine.set_trigger("attack");
         }
 
-        // player.args.jump -= player.args.jump * time.delta_seconds() * 30.0;
-
-        if player.run() {
-            let (_, _, frame_rate) = animation_map.get(player.state.to_str()).unwrap().clone();
-            timer.set_duration(Duration::from_secs_f32(1.0 / frame_rate));
-            // info!("切换 {:?}", player.state);
-            is_timer_done = true;
-        }
-
-        let (state, slot, frame_rate) = animation_map.get(player.state.to_str()).unwrap().clone();
-
-        let display_frame = state.slot.first().unwrap().display_frame.clone();
-
-        if is_timer_done {
-            player.index += 1;
-            if player.index >= display_frame.len() as i32 {
-                player.index = 0;
-                player.args.last_frame = true;
-            }
-        }
-
-        let display = display_frame.get(player.index as usize).unwrap();
-
-        let frame = slot.get(display.value as usize).unwrap();
-        let mut move_v = 0.0;
-
-        if let Some(tr) = frame.transform.clone() {
-            move_v = tr.x;
-            if display.value > 0 {
-                let p_frame = slot.get(display.value as usize - 1).unwrap();
-                if let Some(p_tr) = p_frame.transform.clone() {
-                    move_v = tr.x - p_tr.x;
-                }
-            }
-
-            if sprite.flip_x {
-                move_v *= -1.0;
-            }
-
-            // transform.translation.x += move_v * state.duration as f32 * time.delta_seconds();
+        let down = actions.value(Action::MoveY) < 0.0;
+        if actions.just_pressed(Action::MoveY) && down && state.grounded {
+            drop.start();
         }
+    }
+}
 
-        if !player.args.is_ground {
-            velocity.linvel.x += (player.args.speed * 200.0 - velocity.linvel.x) * 0.7;
+/// Feeds the character's movement to its animations; never moves it.
+fn animate_sprite(
+    pause: Res<Pause>,
+    graphs: Res<Assets<AnimationGraph>>,
+    mut move_events: EventWriter<MoveCameraEvent>,
+    mut query: Query<(
+        &mut TextureAtlasSprite,
+        &mut AnimationStateMachine,
+        &CharacterState,
+        &Transform,
+    )>,
+) {
+    // The state is frozen, and would keep setting the same triggers.
+    if pause.paused {
+        return;
+    }
+    for (mut sprite, mut machine, state, transform) in &mut query {
+        sprite.flip_x = state.facing < 0.0;
+
+        machine.set_bool("is_ground", state.grounded);
+        machine.set_float("speed", state.velocity.x.abs());
+        machine.set_float("velocity_y", state.velocity.y);
+        machine.set_bool("wall_sliding", state.wall_sliding);
+        machine.set_bool("hanging", state.hanging);
+        machine.set_bool("dashing", state.dashing);
+        let trigger = if state.wall_jumped {
+            Some("wall_jump")
+        } else if state.jumped {
+            Some("jump")
         } else {
-            velocity.linvel.x += (move_v * frame_rate - velocity.linvel.x) * 0.7;
-        }
-
-        if is_timer_done {
-            let sp_index = anim.map.get(&frame.name).unwrap().clone();
-            sprite.index = sp_index as usize;
-            // transform.translation.x += move_v;
-            // info!(player.index, move_v, sp_index, frame.name);
+            None
+        };
+        if let (Some(trigger), Some(graph)) = (trigger, graphs.get(&machine.graph)) {
+            if machine.listens_to(graph, trigger) {
+                machine.set_trigger(trigger);
+            }
         }
 
         let translation = transform.translation;